@@ -0,0 +1,141 @@
+//! Disassembly of the machine code around a faulting instruction
+
+use anyhow::Context as _;
+use capstone::prelude::*;
+use colored::Colorize as _;
+
+use crate::registers::Target;
+
+/// Number of bytes disassembled on each side of the faulting instruction
+const WINDOW_SIZE: u32 = 16;
+/// How far before the faulting instruction decoding may start
+const MAX_LEAD: u32 = 256;
+
+/// A decoded instruction, formatted for printing
+#[derive(Debug, PartialEq)]
+struct Instruction {
+    address: u32,
+    bytes: String,
+    text: String,
+}
+
+/// Prints the instructions around `pc`, marking the one at `pc`
+///
+/// Thumb-2 mixes 16- and 32-bit instructions, so decoding has to start at a known instruction
+/// boundary: `function_start`, the start of the function containing `pc`, if it is close enough,
+/// and otherwise `pc` itself.
+pub(crate) fn print(
+    target: &mut dyn Target,
+    pc: u32,
+    function_start: Option<u32>,
+) -> anyhow::Result<()> {
+    let start = match function_start {
+        Some(start) if start <= pc && pc - start <= MAX_LEAD => start,
+        _ => pc,
+    };
+
+    // the target is read in whole words
+    let aligned_start = start & !0b11;
+    let end = pc.saturating_add(WINDOW_SIZE);
+    let mut words = vec![0; ((end - aligned_start) / 4 + 1) as usize];
+    target
+        .read_32(aligned_start, &mut words)
+        .with_context(|| format!("failed to read the code around PC {:#010x}", pc))?;
+    let code = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes().to_vec())
+        .skip((start - aligned_start) as usize)
+        .collect::<Vec<_>>();
+
+    println!(
+        "{}",
+        format!(
+            "disassembly around the faulting instruction at {:#010x}:",
+            pc
+        )
+        .dimmed()
+    );
+    for instruction in window(&code, start, pc)? {
+        let is_faulting = instruction.address == pc;
+        let line = format!(
+            "{} {:#010x}: {:<11}  {}",
+            if is_faulting { "=>" } else { "  " },
+            instruction.address,
+            instruction.bytes,
+            instruction.text,
+        );
+
+        if is_faulting {
+            println!("{}", line.bold());
+        } else {
+            println!("{}", line);
+        }
+    }
+
+    Ok(())
+}
+
+/// Decodes `code`, which starts at the instruction boundary `start`, and returns the instructions
+/// within `WINDOW_SIZE` bytes of `pc`
+fn window(code: &[u8], start: u32, pc: u32) -> anyhow::Result<Vec<Instruction>> {
+    // Cortex-M cores only execute Thumb code
+    let capstone = Capstone::new()
+        .arm()
+        .mode(arch::arm::ArchMode::Thumb)
+        .extra_mode(std::iter::once(arch::arm::ArchExtraMode::MClass))
+        .build()?;
+    let instructions = capstone.disasm_all(code, start as u64)?;
+
+    let first = pc.saturating_sub(WINDOW_SIZE);
+    Ok(instructions
+        .iter()
+        .map(|instruction| Instruction {
+            address: instruction.address() as u32,
+            bytes: instruction
+                .bytes()
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<Vec<_>>()
+                .join(" "),
+            text: format!(
+                "{} {}",
+                instruction.mnemonic().unwrap_or("???"),
+                instruction.op_str().unwrap_or("")
+            ),
+        })
+        .filter(|instruction| {
+            first <= instruction.address && instruction.address < pc.saturating_add(WINDOW_SIZE)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_starting_mid_instruction_stays_in_step() {
+        // a 16-bit `nop` puts the 32-bit `ldr.w r1, [r0, #4]`s off word alignment, so
+        // `(pc - WINDOW_SIZE) & !3` lies within one of them
+        let mut code = vec![0x00, 0xbf];
+        for _ in 0..10 {
+            code.extend_from_slice(&[0xd0, 0xf8, 0x04, 0x10]);
+        }
+        // `udf #0`
+        code.extend_from_slice(&[0x00, 0xde]);
+        let start = 0x100;
+        let pc = start + 2 + 10 * 4;
+
+        let instructions = window(&code, start, pc).unwrap();
+
+        let faulting = instructions
+            .iter()
+            .find(|instruction| instruction.address == pc)
+            .unwrap();
+        assert!(faulting.text.starts_with("udf"), "{:?}", faulting);
+        for instruction in &instructions[..instructions.len() - 1] {
+            assert!(instruction.text.starts_with("ldr.w"), "{:?}", instruction);
+            assert_eq!(2, (instruction.address - start) % 4);
+        }
+    }
+}
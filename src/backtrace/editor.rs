@@ -0,0 +1,117 @@
+//! Opening the location of a backtrace frame in the user's editor
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::dep;
+
+use super::symbolicate::Frame;
+
+/// Opens the innermost frame located in a local source file with `$VISUAL` or `$EDITOR`
+///
+/// Frames in dependencies and files that don't exist on disk are passed over.
+pub(crate) fn open(frames: &[Frame], current_dir: &Path) {
+    let (path, line) = match local_location(frames, current_dir) {
+        Some(location) => location,
+        None => {
+            log::info!("no frame is located in a local source file; not opening an editor");
+            return;
+        }
+    };
+
+    let editor = match env::var("VISUAL").or_else(|_| env::var("EDITOR")) {
+        Ok(editor) if !editor.trim().is_empty() => editor,
+        _ => {
+            log::warn!("`--open-editor` requires `$VISUAL` or `$EDITOR` to be set");
+            return;
+        }
+    };
+
+    // the variable may carry arguments, e.g. `code --wait`
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap();
+    log::info!("opening {}:{} with `{}`", path.display(), line, editor);
+    match Command::new(program)
+        .args(words)
+        .arg(format!("+{}", line))
+        .arg(&path)
+        .status()
+    {
+        Ok(status) if !status.success() => log::warn!("`{}` exited with {}", editor, status),
+        Ok(_) => {}
+        Err(e) => log::warn!("failed to run `{}`: {}", editor, e),
+    }
+}
+
+/// Returns the path and line of the innermost frame located in an existing local source file
+fn local_location(frames: &[Frame], current_dir: &Path) -> Option<(PathBuf, u32)> {
+    frames
+        .iter()
+        .filter_map(|frame| match frame {
+            Frame::Subroutine(subroutine) => subroutine.location.as_ref(),
+            Frame::Exception => None,
+        })
+        .filter(|location| {
+            matches!(
+                dep::Path::from_std_path(&location.path),
+                dep::Path::Verbatim(_)
+            )
+        })
+        .map(|location| (current_dir.join(&location.path), location.line))
+        .find(|(path, _)| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use either::Either;
+
+    use super::super::symbolicate::{Location, Subroutine};
+    use super::*;
+
+    fn frame(path: &str, line: u32) -> Frame {
+        Frame::Subroutine(Subroutine {
+            name_or_pc: Either::Left("function".to_string()),
+            location: Some(Location {
+                column: None,
+                path_is_relative: !path.starts_with('/'),
+                line,
+                path: PathBuf::from(path),
+                is_declaration: false,
+            }),
+            inline_depth: 0,
+            range: None,
+            parameters: None,
+        })
+    }
+
+    #[test]
+    fn dependency_and_missing_files_are_passed_over() {
+        let current_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let frames = [
+            frame(
+                "/home/user/.cargo/registry/src/github.com-1ecc6299db9ec823/cortex-m-rt-0.6.13/src/lib.rs",
+                1,
+            ),
+            frame("src/does_not_exist.rs", 2),
+            Frame::Exception,
+            frame("src/main.rs", 3),
+            frame("src/cli.rs", 4),
+        ];
+
+        assert_eq!(
+            Some((current_dir.join("src/main.rs"), 3)),
+            local_location(&frames, current_dir)
+        );
+    }
+
+    #[test]
+    fn no_location_without_local_frames() {
+        let current_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let frames = [frame("src/does_not_exist.rs", 1)];
+
+        assert_eq!(None, local_location(&frames, current_dir));
+    }
+}
@@ -0,0 +1,104 @@
+//! Raw rows of the DWARF line program, for reports about PCs that map to the wrong line
+
+use std::path::PathBuf;
+
+use crate::elf::Elf;
+
+use super::subprogram;
+
+/// How many rows before and after the one covering the PC are shown
+const CONTEXT: usize = 8;
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct Row {
+    pub(crate) address: u32,
+    pub(crate) path: Option<PathBuf>,
+    pub(crate) line: Option<u64>,
+    /// `None` means the left edge of the line
+    pub(crate) column: Option<u64>,
+    pub(crate) is_stmt: bool,
+    pub(crate) end_sequence: bool,
+}
+
+/// Returns the rows of the sequence that covers `pc`, centered on the row covering it, and that
+/// row's index
+pub(crate) fn rows_around(elf: &Elf, pc: u32) -> anyhow::Result<Option<(Vec<Row>, usize)>> {
+    let dwarf = subprogram::dwarf(elf)?;
+
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let program = match unit.line_program.clone() {
+            Some(program) => program,
+            None => continue,
+        };
+
+        let mut sequence = vec![];
+        let mut rows = program.rows();
+        while let Some((header, row)) = rows.next_row()? {
+            sequence.push(Row {
+                address: row.address() as u32,
+                path: row
+                    .file(header)
+                    .and_then(|file| subprogram::file_path(&dwarf, &unit, header, file)),
+                line: row.line(),
+                column: match row.column() {
+                    gimli::ColumnType::LeftEdge => None,
+                    gimli::ColumnType::Column(column) => Some(column),
+                },
+                is_stmt: row.is_stmt(),
+                end_sequence: row.end_sequence(),
+            });
+
+            if !row.end_sequence() {
+                continue;
+            }
+
+            // the end of a sequence is the first address after it
+            let start = sequence[0].address;
+            if start <= pc && pc < row.address() as u32 {
+                let covering = sequence
+                    .iter()
+                    .rposition(|row| row.address <= pc)
+                    .unwrap_or_default();
+                let first = covering.saturating_sub(CONTEXT);
+                let last = (covering + CONTEXT + 1).min(sequence.len());
+
+                let rows = sequence.drain(first..last).collect();
+                return Ok(Some((rows, covering - first)));
+            }
+            sequence.clear();
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_around_pc_are_read_from_the_test_elf() {
+        let elf_bytes = include_bytes!("../../tests/test_elfs/panic");
+        let elf = Elf::parse(elf_bytes).unwrap();
+
+        // `panic::__cortex_m_rt_main`
+        let (rows, covering) = rows_around(&elf, 0x0000_0196).unwrap().unwrap();
+        let row = &rows[covering];
+
+        assert_eq!(0x0000_0196, row.address);
+        assert_eq!(Some(7), row.line);
+        assert_eq!(Some(5), row.column);
+        assert!(row.path.as_ref().unwrap().ends_with("src/bin/panic.rs"));
+        assert!(row.is_stmt);
+    }
+
+    #[test]
+    fn pc_outside_of_the_program_has_no_rows() {
+        let elf_bytes = include_bytes!("../../tests/test_elfs/panic");
+        let elf = Elf::parse(elf_bytes).unwrap();
+
+        assert_eq!(None, rows_around(&elf, 0xffff_fff0).unwrap());
+    }
+}
@@ -1,43 +1,97 @@
-use std::path::Path;
+use std::{ops::Range, path::Path, str::FromStr};
 
-use probe_rs::{config::RamRegion, Core};
+use anyhow::anyhow;
+use colored::Colorize as _;
+use object::{Object as _, ObjectSection as _};
+use probe_rs::config::RamRegion;
+use regex::Regex;
+use serde_json::{json, Value};
 
-use crate::elf::Elf;
+use crate::{cortexm, dep::PathRules, elf::Elf, events::Events, registers::Target};
 
+#[cfg(feature = "disasm")]
+mod disasm;
+mod editor;
+mod line_program;
 mod pp;
+mod subprogram;
 mod symbolicate;
 mod unwind;
 
 pub(crate) struct Settings<'p> {
     pub(crate) current_dir: &'p Path,
     pub(crate) max_backtrace_len: u32,
-    pub(crate) force_backtrace: bool,
+    pub(crate) backtrace: BacktraceOptions,
     pub(crate) shorten_paths: bool,
+    pub(crate) no_shorten_paths: bool,
+    /// How `shorten_paths` displays the paths of individual crates
+    pub(crate) path_rules: &'p PathRules,
+    pub(crate) backtrace_debug: bool,
+    pub(crate) backtrace_reverse: bool,
+    pub(crate) hide_builtins: bool,
+    /// Only show frames whose source is in the local workspace
+    pub(crate) self_only: bool,
+    /// Show the frames of functions whose name matches, even if another setting hides them
+    pub(crate) keep: Option<&'p Regex>,
+    pub(crate) max_inline_frames: u32,
+    pub(crate) backtrace_style: BacktraceStyle,
+    /// Number of innermost frames to leave out
+    pub(crate) skip: u32,
+    pub(crate) keep_numbers: bool,
+    pub(crate) frame_numbers: FrameNumbers,
+    pub(crate) show_ranges: bool,
+    /// Append the parameter types to the names of (non-inlined) functions
+    pub(crate) signatures: bool,
+    /// Open the innermost local frame in `$VISUAL` or `$EDITOR` after printing the backtrace
+    pub(crate) open_editor: bool,
+    /// Address the program runs at relative to the one it was linked at, e.g. for position
+    /// independent firmware
+    pub(crate) load_base: u32,
+    /// Disassemble the code around the faulting instruction if the program faulted
+    #[cfg(feature = "disasm")]
+    pub(crate) disasm_on_fault: bool,
+}
+
+/// What unwinding the target's program found
+pub(crate) struct Report {
+    pub(crate) outcome: Outcome,
+    /// Number of subroutine frames resolved to a source location, and of all subroutine frames;
+    /// `None` if no backtrace was printed
+    pub(crate) frames: Option<(usize, usize)>,
 }
 
 /// (virtually) unwinds the target's program and prints its backtrace
 pub(crate) fn print(
-    core: &mut Core,
+    target: &mut dyn Target,
     elf: &Elf,
     active_ram_region: &Option<RamRegion>,
     settings: &Settings,
-) -> anyhow::Result<Outcome> {
-    let unwind = unwind::target(core, elf, active_ram_region);
-
-    let frames = symbolicate::frames(&unwind.raw_frames, settings.current_dir, elf);
+    events: &mut Events,
+) -> anyhow::Result<Report> {
+    let unwind = unwind::target(target, elf, active_ram_region, settings.load_base);
 
     let contains_exception = unwind
         .raw_frames
         .iter()
         .any(|raw_frame| raw_frame.is_exception());
 
-    let print_backtrace = settings.force_backtrace
-        || unwind.outcome == Outcome::StackOverflow
-        || unwind.corrupted
-        || contains_exception;
+    let print_backtrace = match settings.backtrace {
+        BacktraceOptions::Always => true,
+        BacktraceOptions::Never => false,
+        BacktraceOptions::Auto => {
+            unwind.outcome == Outcome::StackOverflow || unwind.corrupted || contains_exception
+        }
+    };
 
+    let mut frame_counts = None;
     if print_backtrace && settings.max_backtrace_len > 0 {
-        pp::backtrace(&frames, settings);
+        let mut frames = symbolicate::frames(&unwind.raw_frames, settings.current_dir, elf);
+        subprogram::annotate(&mut frames, elf, settings.current_dir, settings.signatures);
+        frame_counts = Some(pp::backtrace(&frames, settings));
+        events.emit("backtrace", json!({ "frames": frames_to_json(&frames) }))?;
+        if settings.open_editor {
+            editor::open(&frames, settings.current_dir);
+        }
 
         if unwind.corrupted {
             log::warn!("call stack was corrupted; unwinding could not be completed");
@@ -51,7 +105,289 @@ pub(crate) fn print(
         }
     }
 
-    Ok(unwind.outcome)
+    #[cfg(feature = "disasm")]
+    if let (true, Some(pc)) = (settings.disasm_on_fault, faulting_pc(&unwind.raw_frames)) {
+        let function_start = symbolicate::functions_in_range(elf, pc..pc.saturating_add(1))
+            .first()
+            .map(|(range, _)| range.start.wrapping_add(settings.load_base));
+        if let Err(e) = disasm::print(target, pc.wrapping_add(settings.load_base), function_start) {
+            log::warn!("failed to disassemble the faulting instruction: {:?}", e);
+        }
+    }
+
+    Ok(Report {
+        outcome: unwind.outcome,
+        frames: frame_counts,
+    })
+}
+
+/// Returns the PC of the subroutine interrupted by the (first) exception
+#[cfg(feature = "disasm")]
+fn faulting_pc(raw_frames: &[unwind::RawFrame]) -> Option<u32> {
+    match raw_frames
+        .iter()
+        .skip_while(|raw_frame| !raw_frame.is_exception())
+        .nth(1)?
+    {
+        unwind::RawFrame::Subroutine { pc, .. } => Some(*pc),
+        unwind::RawFrame::Exception => None,
+    }
+}
+
+/// The `index` of a frame is its 0-based position in the unfiltered backtrace, whatever the
+/// numbering of the printed backtrace
+fn frames_to_json(frames: &[symbolicate::Frame]) -> Value {
+    frames
+        .iter()
+        .enumerate()
+        .map(|(index, frame)| match frame {
+            symbolicate::Frame::Exception => json!({ "index": index, "exception": true }),
+            symbolicate::Frame::Subroutine(subroutine) => json!({
+                "index": index,
+                "function": subroutine.name_or_pc.as_ref().left(),
+                "pc": subroutine.name_or_pc.as_ref().right(),
+                "file": subroutine
+                    .location
+                    .as_ref()
+                    .map(|location| location.path.display().to_string()),
+                "line": subroutine.location.as_ref().map(|location| location.line),
+                "column": subroutine.location.as_ref().and_then(|location| location.column),
+                "inlined": subroutine.inline_depth > 0,
+            }),
+        })
+        .collect()
+}
+
+/// When to print a backtrace
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum BacktraceOptions {
+    /// Only if the program terminated abnormally
+    Auto,
+    Always,
+    Never,
+}
+
+impl BacktraceOptions {
+    /// Prints the backtrace even if the program ran successfully, unless it is turned off
+    pub(crate) fn force(&mut self) {
+        if *self == Self::Auto {
+            *self = Self::Always;
+        }
+    }
+}
+
+impl FromStr for BacktraceOptions {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => Err(anyhow!(
+                "invalid backtrace option `{}`; expected `always`, `auto` or `never`",
+                s
+            )),
+        }
+    }
+}
+
+/// How inlined frames are laid out
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum BacktraceStyle {
+    /// Every frame on its own numbered line
+    Flat,
+    /// Inlined frames indented below the function they were inlined into
+    Nested,
+}
+
+impl FromStr for BacktraceStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flat" => Ok(Self::Flat),
+            "nested" => Ok(Self::Nested),
+            _ => Err(anyhow!(
+                "invalid backtrace style `{}`; expected `flat` or `nested`",
+                s
+            )),
+        }
+    }
+}
+
+/// The number of the innermost frame in the printed backtrace
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum FrameNumbers {
+    /// Like GDB
+    FromZero,
+    FromOne,
+}
+
+impl FrameNumbers {
+    pub(crate) fn first(self) -> u32 {
+        match self {
+            Self::FromZero => 0,
+            Self::FromOne => 1,
+        }
+    }
+}
+
+impl FromStr for FrameNumbers {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "from-zero" => Ok(Self::FromZero),
+            "from-one" => Ok(Self::FromOne),
+            _ => Err(anyhow!(
+                "invalid frame numbering `{}`; expected `from-zero` or `from-one`",
+                s
+            )),
+        }
+    }
+}
+
+/// Symbolicates the program's entry points without a target attached
+///
+/// Returns the number of frames produced and how many of them have location info
+pub(crate) fn dry_run(elf: &Elf, current_dir: &Path) -> (usize, usize) {
+    let raw_frames = [
+        elf.vector_table.reset,
+        elf.vector_table.hard_fault,
+        elf.main_fn_address(),
+    ]
+    .iter()
+    .map(|&address| unwind::RawFrame::Subroutine {
+        pc: cortexm::clear_thumb_bit(address),
+        is_return_address: false,
+    })
+    .collect::<Vec<_>>();
+
+    let frames = symbolicate::frames(&raw_frames, current_dir, elf);
+    let with_location = frames
+        .iter()
+        .filter(|frame| match frame {
+            symbolicate::Frame::Subroutine(subroutine) => subroutine.location.is_some(),
+            symbolicate::Frame::Exception => false,
+        })
+        .count();
+
+    (frames.len(), with_location)
+}
+
+/// Prints the (inlined) subroutines found at each of the given `addresses`
+pub(crate) fn symbolize(elf: &Elf, addresses: &[u32], settings: &Settings) {
+    for &address in addresses {
+        let raw_frames = [unwind::RawFrame::Subroutine {
+            pc: cortexm::clear_thumb_bit(unwind::link_time_address(address, settings.load_base)),
+            is_return_address: false,
+        }];
+        let mut frames = symbolicate::frames(&raw_frames, settings.current_dir, elf);
+        subprogram::annotate(&mut frames, elf, settings.current_dir, settings.signatures);
+
+        println!("{}", format!("{:#010x}:", address).dimmed());
+        pp::frames(&frames, settings);
+    }
+}
+
+/// Prints the functions whose machine code overlaps `range`, e.g. a flash sector
+pub(crate) fn print_functions_in_range(elf: &Elf, range: Range<u32>) {
+    let functions = symbolicate::functions_in_range(elf, range.clone());
+    println!(
+        "{}",
+        format!(
+            "{} functions in {:#010x}..{:#010x}:",
+            functions.len(),
+            range.start,
+            range.end
+        )
+        .dimmed()
+    );
+
+    for (range, name) in functions {
+        println!("{:#010x}..{:#010x} {}", range.start, range.end, name);
+    }
+}
+
+/// Prints the rows of the DWARF line program around `pc`, exactly as they are in `.debug_line`
+pub(crate) fn print_line_program(elf: &Elf, pc: u32) -> anyhow::Result<()> {
+    let (rows, covering) = line_program::rows_around(elf, pc)?
+        .ok_or_else(|| anyhow!("no line program covers {:#010x}", pc))?;
+
+    println!(
+        "{}",
+        format!("line program rows around {:#010x}:", pc).dimmed()
+    );
+    for (index, row) in rows.iter().enumerate() {
+        let marker = if index == covering { ">" } else { " " };
+        let path = row
+            .path
+            .as_ref()
+            .map(|path| path.display().to_string())
+            .unwrap_or_else(|| "<unknown>".to_string());
+        let line = row.line.unwrap_or(0);
+        let column = row.column.unwrap_or(0);
+
+        let mut flags = vec![];
+        if row.is_stmt {
+            flags.push("is_stmt");
+        }
+        if row.end_sequence {
+            flags.push("end_sequence");
+        }
+
+        println!(
+            "{} {:#010x} -> {}:{}:{} {}",
+            marker,
+            row.address,
+            path,
+            line,
+            column,
+            flags.join(", ").dimmed()
+        );
+    }
+
+    Ok(())
+}
+
+/// Prints how many bytes of machine code each crate contributes to the program
+pub(crate) fn print_crate_sizes(elf: &Elf) {
+    let sizes = symbolicate::crate_sizes(elf);
+    let total: u64 = sizes.iter().map(|(_, size)| size).sum();
+
+    println!(
+        "{}",
+        format!("{:<32} {:>8} {:>7}", "crate", "bytes", "percent").dimmed()
+    );
+    for (name, size) in sizes {
+        let percent = if total == 0 {
+            0.0
+        } else {
+            size as f64 / total as f64 * 100.0
+        };
+        println!("{:<32} {:>8} {:>6.1}%", name, size, percent);
+    }
+    println!(
+        "{}",
+        format!("{:<32} {:>8} {:>6.1}%", "total", total, 100.0).dimmed()
+    );
+}
+
+/// Returns the number of live functions that can be symbolicated with debug info
+pub(crate) fn functions_with_debuginfo(elf: &Elf) -> usize {
+    symbolicate::functions_with_debuginfo(elf).len()
+}
+
+/// Returns the fraction of the `.text` section covered by functions that have debug info
+pub(crate) fn debuginfo_coverage(elf: &Elf) -> Option<f64> {
+    let text_size = elf.section_by_name(".text")?.size();
+    if text_size == 0 {
+        return None;
+    }
+
+    let covered: u64 = symbolicate::functions_with_debuginfo(elf).iter().sum();
+    Some(covered as f64 / text_size as f64)
 }
 
 /// Target program outcome
@@ -59,6 +395,8 @@ pub(crate) fn print(
 pub(crate) enum Outcome {
     HardFault,
     Ok,
+    /// `panic-probe` reported a panic and executed `BKPT`
+    Panic,
     StackOverflow,
 }
 
@@ -68,7 +406,7 @@ impl Outcome {
             Outcome::StackOverflow => {
                 log::error!("the program has overflowed its stack");
             }
-            Outcome::HardFault => {
+            Outcome::HardFault | Outcome::Panic => {
                 log::error!("the program panicked");
             }
             Outcome::Ok => {
@@ -82,7 +420,7 @@ impl Outcome {
 impl From<Outcome> for i32 {
     fn from(outcome: Outcome) -> i32 {
         match outcome {
-            Outcome::HardFault | Outcome::StackOverflow => crate::SIGABRT,
+            Outcome::HardFault | Outcome::Panic | Outcome::StackOverflow => crate::SIGABRT,
             Outcome::Ok => 0,
         }
     }
@@ -1,29 +1,206 @@
 //! Pretty printing the backtrace
 
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashMap, path::Path};
 
 use colored::Colorize as _;
 
 use crate::dep;
 
-use super::{symbolicate::Frame, Settings};
+use super::{
+    symbolicate::{Frame, Location, Subroutine},
+    BacktraceStyle, Settings,
+};
 
-/// Pretty prints processed backtrace frames up to `max_backtrace_len`
-pub(crate) fn backtrace(frames: &[Frame], settings: &Settings) {
+/// Number of unknown frames from which on a mismatch between ELF and device is suspected
+const MIN_UNKNOWN_FOR_HINT: usize = 3;
+
+/// Pretty prints the backtrace header followed by the processed frames
+///
+/// Returns the number of resolved subroutine frames and of all subroutine frames
+pub(crate) fn backtrace(frames: &[Frame], settings: &Settings) -> (usize, usize) {
     println!("{}", "stack backtrace:".dimmed());
+    self::frames(frames, settings);
+
+    let (num_resolved, num_frames) = count_resolved(frames);
+    let num_unknown = num_frames - num_resolved;
+    println!(
+        "{}",
+        format!(
+            "resolved {}/{} frames ({} unknown)",
+            num_resolved, num_frames, num_unknown
+        )
+        .dimmed()
+    );
+
+    // the symbol table covers every function, so unknown frames point to a different program
+    if num_unknown >= MIN_UNKNOWN_FOR_HINT && num_unknown * 2 > num_frames {
+        log::warn!(
+            "most frames have no symbol; does the ELF file match the program on the device?"
+        );
+    }
+
+    (num_resolved, num_frames)
+}
+
+/// Returns how many subroutine frames have a symbol, and how many there are
+///
+/// A frame without a location still resolved to a function, e.g. one from a library built
+/// without debug info.
+fn count_resolved(frames: &[Frame]) -> (usize, usize) {
+    let subroutines = frames.iter().filter_map(|frame| match frame {
+        Frame::Subroutine(subroutine) => Some(subroutine),
+        Frame::Exception => None,
+    });
+
+    subroutines.fold((0, 0), |(resolved, total), subroutine| {
+        (
+            resolved + subroutine.name_or_pc.is_left() as usize,
+            total + 1,
+        )
+    })
+}
+
+/// Pretty prints processed backtrace frames up to `max_backtrace_len`
+pub(crate) fn frames(frames: &[Frame], settings: &Settings) {
+    let Entries {
+        mut entries,
+        truncated,
+        num_hidden_builtins,
+        num_hidden_external,
+    } = entries(frames, settings);
+
+    if settings.backtrace_reverse {
+        entries.reverse();
+    }
+
+    for entry in entries {
+        println!("{}", entry);
+    }
+
+    if num_hidden_builtins != 0 {
+        let note = format!(
+            "      ({} compiler-builtins frames hidden)",
+            num_hidden_builtins
+        );
+        println!("{}", note.dimmed());
+    }
+
+    if num_hidden_external != 0 {
+        let note = format!("      ({} external frames hidden)", num_hidden_external);
+        println!("{}", note.dimmed());
+    }
+
+    if truncated {
+        log::warn!(
+            "maximum backtrace length of {} reached; cutting off the rest.const ",
+            settings.max_backtrace_len
+        );
+        log::warn!("note: re-run with `--max-backtrace-len=<your maximum>` to extend this limit");
+    }
+}
+
+/// The text of the frames to print, and what was left out
+struct Entries {
+    /// One per shown frame, innermost first
+    entries: Vec<String>,
+    truncated: bool,
+    num_hidden_builtins: usize,
+    num_hidden_external: usize,
+}
+
+fn entries(frames: &[Frame], settings: &Settings) -> Entries {
+    // the text of each frame; it is only printed at the end so the order can be reversed
+    let mut entries = vec![];
+    let mut truncated = false;
+    let mut num_hidden_builtins = 0;
+    let mut num_hidden_external = 0;
+    // `--backtrace-self-only` shows the innermost frame if none is local, so the output isn't empty
+    let keep_innermost = settings.self_only && !frames.iter().any(is_local_frame);
+    // inlined frames at the PC currently being printed
+    let mut num_inlined_shown = 0;
+    let mut num_inlined_hidden = 0;
+    // `--backtrace-style nested`: the inlined frames waiting for their function, with call sites
+    let mut inlined: Vec<(&Subroutine, Option<&Location>)> = vec![];
+    let nested = settings.backtrace_style == BacktraceStyle::Nested;
+    // formatted path of each source file, see `format_location`
+    let mut paths = HashMap::new();
+
+    let mut num_skipped = 0;
+    // with `--backtrace-keep-numbers` the shown frames keep the number they would have had
+    let first_frame_number = if settings.keep_numbers {
+        settings.frame_numbers.first() + settings.skip
+    } else {
+        settings.frame_numbers.first()
+    };
 
     let mut frame_index = 0;
     for frame in frames {
+        if num_skipped < settings.skip {
+            if let Frame::Subroutine(_) = frame {
+                num_skipped += 1;
+            }
+            continue;
+        }
+
         match frame {
             Frame::Exception => {
-                println!("      <exception entry>");
+                entries.push("      <exception entry>".to_string());
             }
 
             Frame::Subroutine(subroutine) => {
-                let name = match &subroutine.name_or_pc {
-                    either::Either::Left(name) => Cow::Borrowed(name),
-                    either::Either::Right(pc) => Cow::Owned(format!("??? (PC={:#010x})", pc)),
-                };
+                // the inlined frames hidden from this function's entry, noted after it
+                let mut num_more_inlined = 0;
+                if subroutine.inline_depth > 0 {
+                    if num_inlined_shown >= settings.max_inline_frames {
+                        num_inlined_hidden += 1;
+                        continue;
+                    }
+                    num_inlined_shown += 1;
+                } else {
+                    num_more_inlined = num_inlined_hidden;
+                    num_inlined_shown = 0;
+                    num_inlined_hidden = 0;
+                }
+
+                // the location of a subroutine is the call site of the one inlined into it
+                if let Some((_, call_site)) = inlined.last_mut() {
+                    *call_site = subroutine.location.as_ref();
+                }
+
+                // `--backtrace-keep` takes precedence over every filter that hides frames
+                let keep = settings.keep.map_or(false, |regex| {
+                    subroutine
+                        .name_or_pc
+                        .as_ref()
+                        .left()
+                        .map_or(false, |name| regex.is_match(name))
+                });
+
+                let is_shown_external = keep_innermost && entries.is_empty();
+                let hidden_builtin =
+                    !keep && settings.hide_builtins && is_compiler_builtins(subroutine);
+                let hidden_external =
+                    !keep && settings.self_only && !is_local(subroutine) && !is_shown_external;
+                if hidden_builtin || hidden_external {
+                    if hidden_builtin {
+                        num_hidden_builtins += 1;
+                    } else {
+                        num_hidden_external += 1;
+                    }
+                    // the inlined frames waiting for a hidden function go with it, and
+                    // `num_more_inlined` with them
+                    if subroutine.inline_depth == 0 {
+                        inlined.clear();
+                    }
+                    continue;
+                }
+
+                if nested && subroutine.inline_depth > 0 {
+                    inlined.push((subroutine, None));
+                    continue;
+                }
+
+                let name = format_name(subroutine);
 
                 let is_local_function = subroutine
                     .location
@@ -31,44 +208,235 @@ pub(crate) fn backtrace(frames: &[Frame], settings: &Settings) {
                     .map(|location| location.path_is_relative)
                     .unwrap_or(false);
 
-                let line = format!("{:>4}: {}", frame_index, name);
+                let debug_info = if settings.backtrace_debug {
+                    let inline = if subroutine.inline_depth > 0 {
+                        ",inline"
+                    } else {
+                        ""
+                    };
+                    Cow::Owned(format!("[d={}{}] ", subroutine.inline_depth, inline))
+                } else {
+                    Cow::Borrowed("")
+                };
+
+                let range = match &subroutine.range {
+                    Some(range) if settings.show_ranges => {
+                        Cow::Owned(format!(" [{:#010x}..{:#010x}]", range.start, range.end))
+                    }
+                    _ => Cow::Borrowed(""),
+                };
+
+                let line = format!(
+                    "{:>4}: {}{}{}",
+                    first_frame_number + frame_index,
+                    debug_info,
+                    name,
+                    range
+                );
                 let colorized_line = if is_local_function {
                     line.bold()
                 } else {
                     line.normal()
                 };
-                println!("{}", colorized_line);
+                let mut entry = colorized_line.to_string();
 
                 if let Some(location) = &subroutine.location {
-                    let dep_path = dep::Path::from_std_path(&location.path);
-
-                    let path = if settings.shorten_paths {
-                        dep_path.format_short()
-                    } else {
-                        dep_path.format_highlight()
-                    };
+                    entry.push_str(&format!(
+                        "\n        at {}",
+                        format_location(location, settings, &mut paths)
+                    ));
+                }
 
-                    let line = location.line;
-                    let column = location
-                        .column
-                        .map(|column| Cow::Owned(format!(":{}", column)))
+                // outermost inlined subroutine first, each one indented below its caller
+                for (depth, (subroutine, call_site)) in inlined.drain(..).rev().enumerate() {
+                    let indent = " ".repeat(8 + 3 * depth);
+                    let call_site = call_site
+                        .map(|location| {
+                            Cow::Owned(format!(
+                                " (inlined at {})",
+                                format_location(location, settings, &mut paths)
+                            ))
+                        })
                         .unwrap_or(Cow::Borrowed(""));
+                    entry.push_str(&format!(
+                        "\n{}└─ {}{}",
+                        indent,
+                        format_name(subroutine),
+                        call_site.dimmed()
+                    ));
 
-                    println!("        at {}:{}{}", path, line, column);
+                    if let Some(location) = &subroutine.location {
+                        entry.push_str(&format!(
+                            "\n{}     at {}",
+                            indent,
+                            format_location(location, settings, &mut paths)
+                        ));
+                    }
                 }
 
+                entries.push(entry);
+                if num_more_inlined != 0 {
+                    entries.push(format!("      (+{} more inlined)", num_more_inlined));
+                }
                 frame_index += 1;
 
                 if frame_index >= settings.max_backtrace_len {
-                    log::warn!(
-                        "maximum backtrace length of {} reached; cutting off the rest.const ",
-                        settings.max_backtrace_len
-                    );
-                    log::warn!("note: re-run with `--max-backtrace-len=<your maximum>` to extend this limit");
-
+                    truncated = true;
                     break;
                 }
             }
         }
     }
+
+    Entries {
+        entries,
+        truncated,
+        num_hidden_builtins,
+        num_hidden_external,
+    }
+}
+
+fn format_name(subroutine: &Subroutine) -> Cow<str> {
+    match (&subroutine.name_or_pc, &subroutine.parameters) {
+        (either::Either::Left(name), None) => Cow::Borrowed(name),
+        (either::Either::Left(name), Some(parameters)) => {
+            Cow::Owned(format!("{}({})", name, parameters.join(", ")))
+        }
+        (either::Either::Right(pc), _) => Cow::Owned(format!("??? (PC={:#010x})", pc)),
+    }
+}
+
+/// Formats `location` as `path:line:column`, marking a declaration site as such
+///
+/// Many frames usually share a path, so each path is only classified and formatted once
+fn format_location<'f>(
+    location: &'f Location,
+    settings: &Settings,
+    paths: &mut HashMap<&'f Path, String>,
+) -> String {
+    let path = paths.entry(location.path.as_path()).or_insert_with(|| {
+        if settings.no_shorten_paths {
+            location.path.display().to_string()
+        } else {
+            let dep_path = dep::Path::from_std_path(&location.path);
+
+            if settings.shorten_paths {
+                dep_path.format_short(settings.path_rules)
+            } else {
+                dep_path.format_highlight()
+            }
+        }
+    });
+
+    let column = location
+        .column
+        .map(|column| Cow::Owned(format!(":{}", column)))
+        .unwrap_or(Cow::Borrowed(""));
+
+    let declaration = if location.is_declaration {
+        " (declaration)"
+    } else {
+        ""
+    };
+
+    format!("{}:{}{}{}", path, location.line, column, declaration)
+}
+
+/// Checks if the frame's source is in the local workspace rather than std or a dependency
+fn is_local(subroutine: &Subroutine) -> bool {
+    subroutine.location.as_ref().map_or(false, |location| {
+        matches!(
+            dep::Path::from_std_path(&location.path),
+            dep::Path::Verbatim(_)
+        )
+    })
+}
+
+fn is_local_frame(frame: &Frame) -> bool {
+    match frame {
+        Frame::Subroutine(subroutine) => is_local(subroutine),
+        Frame::Exception => false,
+    }
+}
+
+fn is_compiler_builtins(subroutine: &Subroutine) -> bool {
+    let name_matches = subroutine
+        .name_or_pc
+        .as_ref()
+        .left()
+        .map_or(false, |name| name.starts_with("compiler_builtins::"));
+    let path_matches = subroutine.location.as_ref().map_or(false, |location| {
+        dep::Path::from_std_path(&location.path).is_compiler_builtins()
+    });
+
+    name_matches || path_matches
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use either::Either;
+
+    use crate::dep::PathRules;
+
+    use super::{super::BacktraceOptions, super::FrameNumbers, *};
+
+    fn subroutine(name: &str, path: &str, inline_depth: usize) -> Frame {
+        Frame::Subroutine(Subroutine {
+            name_or_pc: Either::Left(name.to_string()),
+            location: Some(Location {
+                column: None,
+                path_is_relative: true,
+                line: 1,
+                path: PathBuf::from(path),
+                is_declaration: false,
+            }),
+            inline_depth,
+            range: None,
+            parameters: None,
+        })
+    }
+
+    #[test]
+    fn inlined_frames_of_a_hidden_function_are_hidden_with_it() {
+        let current_dir = PathBuf::from("/work");
+        let path_rules = PathRules::default();
+        let settings = Settings {
+            current_dir: &current_dir,
+            max_backtrace_len: 50,
+            backtrace: BacktraceOptions::Always,
+            shorten_paths: false,
+            no_shorten_paths: true,
+            path_rules: &path_rules,
+            backtrace_debug: false,
+            backtrace_reverse: false,
+            hide_builtins: true,
+            self_only: false,
+            keep: None,
+            max_inline_frames: 8,
+            backtrace_style: BacktraceStyle::Nested,
+            skip: 0,
+            keep_numbers: false,
+            frame_numbers: FrameNumbers::FromZero,
+            show_ranges: false,
+            signatures: false,
+            open_editor: false,
+            load_base: 0,
+            #[cfg(feature = "disasm")]
+            disasm_on_fault: false,
+        };
+        let frames = [
+            subroutine("app::helper", "src/lib.rs", 1),
+            subroutine("compiler_builtins::mem::memcpy", "src/mem.rs", 0),
+            subroutine("app::main", "src/main.rs", 0),
+        ];
+
+        let entries = entries(&frames, &settings);
+
+        assert_eq!(1, entries.num_hidden_builtins);
+        assert_eq!(1, entries.entries.len());
+        assert!(entries.entries[0].contains("app::main"));
+        assert!(!entries.entries[0].contains("app::helper"));
+    }
 }
@@ -0,0 +1,468 @@
+//! Details of non-inlined functions that addr2line doesn't report, read from their
+//! `DW_TAG_subprogram` DIEs
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use gimli::{AttributeValue, EndianSlice, EntriesTreeNode};
+
+use crate::{cortexm, elf::Elf};
+
+use super::symbolicate::{Frame, Location};
+
+pub(super) type Reader<'file> = EndianSlice<'file, cortexm::Endianness>;
+
+/// Name of a parameter type that has no name in the debug info
+const UNKNOWN: &str = "<unknown>";
+/// How many `DW_AT_abstract_origin` links are followed; guards against cycles in broken debug info
+const MAX_ORIGIN_DEPTH: usize = 16;
+
+/// What to read for the function starting at an address
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Wanted {
+    parameters: bool,
+    declaration: bool,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Details {
+    /// Names of the parameter types
+    parameters: Option<Vec<String>>,
+    /// File and line the function is declared at
+    declaration: Option<(PathBuf, u32)>,
+}
+
+/// Attaches the parameter types (if `signatures`) and, to frames without a location, the
+/// declaration site of non-inlined functions
+///
+/// This walks all of `.debug_info`, so it is only done if there is something to attach.
+pub(crate) fn annotate(frames: &mut [Frame], elf: &Elf, current_dir: &Path, signatures: bool) {
+    let mut wanted = HashMap::<u32, Wanted>::new();
+    for frame in frames.iter() {
+        if let Frame::Subroutine(subroutine) = frame {
+            // a frame without a symbol has nothing to point its declaration at
+            let declaration = subroutine.location.is_none() && subroutine.name_or_pc.is_left();
+            if !signatures && !declaration {
+                continue;
+            }
+
+            if let Some(range) = &subroutine.range {
+                let wanted = wanted.entry(range.start).or_default();
+                wanted.parameters |= signatures;
+                wanted.declaration |= declaration;
+            }
+        }
+    }
+    if wanted.is_empty() {
+        return;
+    }
+
+    let mut details = match dwarf(elf).and_then(|dwarf| read_details(&dwarf, &wanted)) {
+        Ok(details) => details,
+        Err(e) => {
+            log::warn!(
+                "failed to read the details of functions from the debug info: {}",
+                e
+            );
+            return;
+        }
+    };
+
+    for frame in frames {
+        let subroutine = match frame {
+            Frame::Subroutine(subroutine) => subroutine,
+            Frame::Exception => continue,
+        };
+        let details = match subroutine
+            .range
+            .as_ref()
+            .and_then(|range| details.get_mut(&range.start))
+        {
+            Some(details) => details,
+            None => continue,
+        };
+
+        subroutine.parameters = details.parameters.clone();
+        if subroutine.location.is_none() {
+            subroutine.location = details.declaration.as_ref().map(|(path, line)| {
+                let (path, path_is_relative) = match path.strip_prefix(current_dir) {
+                    Ok(relpath) => (relpath, true),
+                    Err(_) => (path.as_path(), false),
+                };
+
+                Location {
+                    column: None,
+                    path_is_relative,
+                    line: *line,
+                    path: path.to_owned(),
+                    is_declaration: true,
+                }
+            });
+        }
+    }
+}
+
+/// Returns the details of the functions starting at the addresses in `wanted`
+fn read_details(
+    dwarf: &gimli::Dwarf<Reader>,
+    wanted: &HashMap<u32, Wanted>,
+) -> anyhow::Result<HashMap<u32, Details>> {
+    let mut details = HashMap::new();
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        // one malformed unit shouldn't cost the details found in the others
+        let result = dwarf.unit(header).and_then(|unit| {
+            let unit = Unit { dwarf, unit };
+            let mut tree = unit.unit.entries_tree(None)?;
+            unit.walk(tree.root()?, wanted, &mut details)
+        });
+        if let Err(e) = result {
+            log::debug!("skipping a unit whose debug info could not be read: {}", e);
+        }
+    }
+
+    Ok(details)
+}
+
+struct Unit<'a, 'file> {
+    dwarf: &'a gimli::Dwarf<Reader<'file>>,
+    unit: gimli::Unit<Reader<'file>>,
+}
+
+impl<'a, 'file> Unit<'a, 'file> {
+    /// Collects the details of the subprograms below `node` that start at an address in `wanted`
+    fn walk(
+        &self,
+        node: EntriesTreeNode<Reader<'file>>,
+        wanted: &HashMap<u32, Wanted>,
+        details: &mut HashMap<u32, Details>,
+    ) -> gimli::Result<()> {
+        let entry = node.entry();
+        if entry.tag() == gimli::DW_TAG_subprogram {
+            if let Some(AttributeValue::Addr(low_pc)) = entry.attr_value(gimli::DW_AT_low_pc)? {
+                let low_pc = low_pc as u32;
+                if let Some(&wanted) = wanted.get(&low_pc) {
+                    let declaration = if wanted.declaration {
+                        self.declaration(entry)?
+                    } else {
+                        None
+                    };
+                    let parameters = if wanted.parameters {
+                        Some(self.formal_parameters(node)?)
+                    } else {
+                        None
+                    };
+
+                    details.insert(
+                        low_pc,
+                        Details {
+                            parameters,
+                            declaration,
+                        },
+                    );
+                    return Ok(());
+                }
+            }
+        }
+
+        // subprograms are nested in namespaces and `impl` blocks
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            self.walk(child, wanted, details)?;
+        }
+
+        Ok(())
+    }
+
+    fn formal_parameters(
+        &self,
+        node: EntriesTreeNode<Reader<'file>>,
+    ) -> gimli::Result<Vec<String>> {
+        let mut types = vec![];
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+            if entry.tag() != gimli::DW_TAG_formal_parameter {
+                continue;
+            }
+
+            let ty = match self.origin_attr(entry, gimli::DW_AT_type)? {
+                Some(AttributeValue::UnitRef(offset)) => Some(self.unit.entry(offset)?),
+                _ => None,
+            };
+
+            let name = match ty {
+                Some(ty) => self.name(&ty)?,
+                None => None,
+            };
+            types.push(name.unwrap_or_else(|| UNKNOWN.to_string()));
+        }
+
+        Ok(types)
+    }
+
+    fn name(
+        &self,
+        entry: &gimli::DebuggingInformationEntry<Reader<'file>>,
+    ) -> gimli::Result<Option<String>> {
+        Ok(match entry.attr_value(gimli::DW_AT_name)? {
+            Some(name) => self
+                .dwarf
+                .attr_string(&self.unit, name)
+                .ok()
+                .map(|name| name.to_string_lossy().into_owned()),
+            None => None,
+        })
+    }
+
+    /// Returns the file and line the subprogram is declared at, according to `DW_AT_decl_file` and
+    /// `DW_AT_decl_line`
+    fn declaration(
+        &self,
+        entry: &gimli::DebuggingInformationEntry<Reader<'file>>,
+    ) -> gimli::Result<Option<(PathBuf, u32)>> {
+        let file = match self.origin_attr(entry, gimli::DW_AT_decl_file)? {
+            Some(AttributeValue::FileIndex(index)) => self.file_path(index),
+            _ => None,
+        };
+        let line = self
+            .origin_attr(entry, gimli::DW_AT_decl_line)?
+            .and_then(|line| line.udata_value());
+
+        Ok(match (file, line) {
+            (Some(file), Some(line)) => Some((file, line as u32)),
+            _ => None,
+        })
+    }
+
+    /// Returns the value of `attr` of `entry`, or of the DIE its `DW_AT_abstract_origin` refers to
+    ///
+    /// The out-of-line instance of a function that has also been inlined somewhere refers to the
+    /// abstract instance for its declaration, which may itself refer to another abstract instance,
+    /// so the chain is followed until a DIE has `attr`.
+    fn origin_attr(
+        &self,
+        entry: &gimli::DebuggingInformationEntry<Reader<'file>>,
+        attr: gimli::DwAt,
+    ) -> gimli::Result<Option<AttributeValue<Reader<'file>>>> {
+        let mut entry = entry.clone();
+        for _ in 0..=MAX_ORIGIN_DEPTH {
+            if let Some(value) = entry.attr_value(attr)? {
+                return Ok(Some(value));
+            }
+
+            entry = match entry.attr_value(gimli::DW_AT_abstract_origin)? {
+                Some(AttributeValue::UnitRef(offset)) => self.unit.entry(offset)?,
+                _ => return Ok(None),
+            };
+        }
+
+        log::debug!(
+            "gave up following `DW_AT_abstract_origin` after {} links",
+            MAX_ORIGIN_DEPTH
+        );
+        Ok(None)
+    }
+
+    /// Resolves a file index through the unit's line program header
+    fn file_path(&self, index: u64) -> Option<PathBuf> {
+        let header = self.unit.line_program.as_ref()?.header();
+        file_path(self.dwarf, &self.unit, header, header.file(index)?)
+    }
+}
+
+/// Returns the sections of `elf` that describe functions and their lines
+pub(super) fn dwarf<'file>(elf: &Elf<'file>) -> anyhow::Result<gimli::Dwarf<Reader<'file>>> {
+    Ok(gimli::Dwarf {
+        debug_abbrev: gimli::DebugAbbrev::new(
+            elf.section_data(".debug_abbrev")?,
+            cortexm::ENDIANNESS,
+        ),
+        debug_info: gimli::DebugInfo::new(elf.section_data(".debug_info")?, cortexm::ENDIANNESS),
+        debug_line: gimli::DebugLine::new(elf.section_data(".debug_line")?, cortexm::ENDIANNESS),
+        debug_line_str: gimli::DebugLineStr::new(
+            elf.section_data(".debug_line_str")?,
+            cortexm::ENDIANNESS,
+        ),
+        debug_str: gimli::DebugStr::new(elf.section_data(".debug_str")?, cortexm::ENDIANNESS),
+        ..Default::default()
+    })
+}
+
+/// Joins the compilation directory, the directory and the name of `file`
+pub(super) fn file_path<'file>(
+    dwarf: &gimli::Dwarf<Reader<'file>>,
+    unit: &gimli::Unit<Reader<'file>>,
+    header: &gimli::LineProgramHeader<Reader<'file>>,
+    file: &gimli::FileEntry<Reader<'file>>,
+) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    if let Some(comp_dir) = &unit.comp_dir {
+        path.push(&*comp_dir.to_string_lossy());
+    }
+    if let Some(directory) = file.directory(header) {
+        let directory = dwarf.attr_string(unit, directory).ok()?;
+        path.push(&*directory.to_string_lossy());
+    }
+    let name = dwarf.attr_string(unit, file.path_name()).ok()?;
+    path.push(&*name.to_string_lossy());
+
+    Some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use gimli::write::{self, EndianVec, Sections};
+
+    use super::*;
+
+    fn fixture_details(address: u32, wanted: Wanted) -> Option<Details> {
+        let elf_bytes = include_bytes!("../../tests/test_elfs/panic");
+        let elf = Elf::parse(elf_bytes).unwrap();
+
+        let wanted = vec![(address, wanted)].into_iter().collect();
+        read_details(&dwarf(&elf).unwrap(), &wanted)
+            .unwrap()
+            .remove(&address)
+    }
+
+    const PARAMETERS: Wanted = Wanted {
+        parameters: true,
+        declaration: false,
+    };
+
+    /// Writes a unit with a function at `0x100` whose single parameter refers to the parameter of
+    /// an abstract instance, which `link_origin` links to the parameter that has the type
+    fn chained_origins(
+        link_origin: impl FnOnce(&mut write::Unit, write::UnitEntryId, write::UnitEntryId),
+    ) -> Sections<EndianVec<cortexm::Endianness>> {
+        let encoding = gimli::Encoding {
+            format: gimli::Format::Dwarf32,
+            version: 4,
+            address_size: 4,
+        };
+        let mut dwarf = write::DwarfUnit::new(encoding);
+        let unit = &mut dwarf.unit;
+        let root = unit.root();
+
+        let ty = unit.add(root, gimli::DW_TAG_base_type);
+        unit.get_mut(ty).set(
+            gimli::DW_AT_name,
+            write::AttributeValue::String(b"u32".to_vec()),
+        );
+
+        // `#[inline] fn f(x: u32)`, its abstract instance in another inlined function, and its
+        // out-of-line instance
+        let abstract_function = unit.add(root, gimli::DW_TAG_subprogram);
+        let abstract_parameter = unit.add(abstract_function, gimli::DW_TAG_formal_parameter);
+        unit.get_mut(abstract_parameter).set(
+            gimli::DW_AT_type,
+            write::AttributeValue::ThisUnitEntryRef(ty),
+        );
+
+        let inlined_function = unit.add(root, gimli::DW_TAG_subprogram);
+        let inlined_parameter = unit.add(inlined_function, gimli::DW_TAG_formal_parameter);
+        link_origin(unit, inlined_parameter, abstract_parameter);
+
+        let function = unit.add(root, gimli::DW_TAG_subprogram);
+        unit.get_mut(function).set(
+            gimli::DW_AT_low_pc,
+            write::AttributeValue::Address(write::Address::Constant(0x100)),
+        );
+        let parameter = unit.add(function, gimli::DW_TAG_formal_parameter);
+        unit.get_mut(parameter).set(
+            gimli::DW_AT_abstract_origin,
+            write::AttributeValue::ThisUnitEntryRef(inlined_parameter),
+        );
+
+        let mut sections = Sections::new(EndianVec::new(cortexm::ENDIANNESS));
+        dwarf.write(&mut sections).unwrap();
+        sections
+    }
+
+    fn parameters_of(sections: &Sections<EndianVec<cortexm::Endianness>>) -> Option<Vec<String>> {
+        let dwarf = gimli::Dwarf {
+            debug_abbrev: gimli::DebugAbbrev::new(
+                sections.debug_abbrev.slice(),
+                cortexm::ENDIANNESS,
+            ),
+            debug_info: gimli::DebugInfo::new(sections.debug_info.slice(), cortexm::ENDIANNESS),
+            debug_str: gimli::DebugStr::new(sections.debug_str.slice(), cortexm::ENDIANNESS),
+            ..Default::default()
+        };
+
+        let wanted = vec![(0x100, PARAMETERS)].into_iter().collect();
+        read_details(&dwarf, &wanted)
+            .unwrap()
+            .remove(&0x100)
+            .unwrap()
+            .parameters
+    }
+
+    #[test]
+    fn abstract_origins_are_followed_transitively() {
+        let sections = chained_origins(|unit, inlined_parameter, abstract_parameter| {
+            unit.get_mut(inlined_parameter).set(
+                gimli::DW_AT_abstract_origin,
+                write::AttributeValue::ThisUnitEntryRef(abstract_parameter),
+            );
+        });
+
+        assert_eq!(Some(vec!["u32".to_string()]), parameters_of(&sections));
+    }
+
+    #[test]
+    fn cycle_of_abstract_origins_has_an_unknown_type() {
+        // the inlined parameter refers to itself instead of the abstract one
+        let sections = chained_origins(|unit, inlined_parameter, _| {
+            unit.get_mut(inlined_parameter).set(
+                gimli::DW_AT_abstract_origin,
+                write::AttributeValue::ThisUnitEntryRef(inlined_parameter),
+            );
+        });
+
+        assert_eq!(Some(vec![UNKNOWN.to_string()]), parameters_of(&sections));
+    }
+
+    #[test]
+    fn parameter_types_are_read_from_the_test_elf() {
+        // `<defmt_rtt::Logger as defmt::Write>::write`
+        let details = fixture_details(0x0000_023a, PARAMETERS);
+
+        assert_eq!(
+            Some(vec![
+                "&mut defmt_rtt::Logger".to_string(),
+                "&[u8]".to_string()
+            ]),
+            details.unwrap().parameters
+        );
+    }
+
+    #[test]
+    fn functions_without_parameters_have_an_empty_list() {
+        // `panic::__cortex_m_rt_main`
+        let details = fixture_details(0x0000_0196, PARAMETERS);
+
+        assert_eq!(Some(vec![]), details.unwrap().parameters);
+    }
+
+    #[test]
+    fn declaration_site_is_read_from_the_test_elf() {
+        // `panic::__cortex_m_rt_main`, declared at line 7 of `src/bin/panic.rs`
+        let details = fixture_details(
+            0x0000_0196,
+            Wanted {
+                parameters: false,
+                declaration: true,
+            },
+        )
+        .unwrap();
+
+        let (path, line) = details.declaration.unwrap();
+        assert!(path.ends_with("src/bin/panic.rs"), "{}", path.display());
+        assert_eq!(7, line);
+        assert_eq!(None, details.parameters);
+    }
+}
@@ -1,7 +1,8 @@
 //! Turns PC addresses into function names and locations
 
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
+    ops::Range,
     path::{Path, PathBuf},
     rc::Rc,
 };
@@ -9,25 +10,41 @@ use std::{
 use addr2line::fallible_iterator::FallibleIterator as _;
 use either::Either;
 use gimli::{EndianReader, RunTimeEndian};
-use object::{Object as _, SymbolMap, SymbolMapName};
+use object::{Object as _, ObjectSymbol as _, SymbolKind, SymbolMap, SymbolMapName};
 
-use crate::{cortexm, elf::Elf};
+use crate::{cortexm, dep, elf::Elf};
 
 use super::unwind::RawFrame;
 
 pub(crate) fn frames(raw_frames: &[RawFrame], current_dir: &Path, elf: &Elf) -> Vec<Frame> {
     let mut frames = vec![];
 
-    let symtab = elf.symbol_map();
-    let addr2line = addr2line::Context::new(&**elf).ok();
+    let symtab = Symtab::new(elf);
+    // units are only parsed when a PC falls into them, so a corrupt one only costs the locations
+    // of its own frames; but if not even the unit headers can be read, all frames are named from
+    // the symbol table
+    let addr2line = match addr2line::Context::new(&**elf) {
+        Ok(addr2line) => Some(addr2line),
+        Err(e) => {
+            log::warn!(
+                "failed to read the debug info ({}); backtrace frames will have no locations",
+                e
+            );
+            None
+        }
+    };
 
     for raw_frame in raw_frames {
         match raw_frame {
             RawFrame::Exception => frames.push(Frame::Exception),
 
-            RawFrame::Subroutine { pc } => {
+            RawFrame::Subroutine {
+                pc,
+                is_return_address,
+            } => {
                 for subroutine in Subroutine::from_pc(
                     *pc,
+                    *is_return_address,
                     addr2line.as_ref(),
                     &elf.live_functions,
                     current_dir,
@@ -42,6 +59,102 @@ pub(crate) fn frames(raw_frames: &[RawFrame], current_dir: &Path, elf: &Elf) ->
     frames
 }
 
+/// Returns the sizes of the live functions that have debug info attached
+pub(crate) fn functions_with_debuginfo(elf: &Elf) -> Vec<u64> {
+    let addr2line = match addr2line::Context::new(&**elf) {
+        Ok(addr2line) => addr2line,
+        Err(_) => return vec![],
+    };
+
+    elf.symbols()
+        .filter(|symbol| symbol.kind() == SymbolKind::Text)
+        .filter(|symbol| {
+            symbol
+                .name()
+                .map_or(false, |name| elf.live_functions.contains(name))
+        })
+        .filter(|symbol| {
+            let address = cortexm::clear_thumb_bit(symbol.address() as u32);
+            addr2line
+                .find_frames(address as u64)
+                .ok()
+                .and_then(|mut frames| frames.next().ok().flatten())
+                .map_or(false, |frame| frame.function.is_some())
+        })
+        .map(|symbol| symbol.size())
+        .collect()
+}
+
+/// Returns the functions whose machine code overlaps `range`, ordered by address
+pub(crate) fn functions_in_range(elf: &Elf, range: Range<u32>) -> Vec<(Range<u32>, String)> {
+    Symtab::new(elf)
+        .functions_in_range(range)
+        .into_iter()
+        .map(|(range, name)| (range, demangle(name)))
+        .collect()
+}
+
+/// Returns the number of bytes of live functions that belong to each crate, largest first
+///
+/// A function belongs to the crate of its source file if that is std or a crates.io dependency,
+/// and otherwise to the crate its symbol name starts with.
+pub(crate) fn crate_sizes(elf: &Elf) -> Vec<(String, u64)> {
+    let addr2line = addr2line::Context::new(&**elf).ok();
+
+    let mut sizes = HashMap::new();
+    for symbol in elf.symbols() {
+        let name = match symbol.name() {
+            Ok(name) if symbol.kind() == SymbolKind::Text && elf.live_functions.contains(name) => {
+                name
+            }
+            _ => continue,
+        };
+
+        let address = cortexm::clear_thumb_bit(symbol.address() as u32);
+        let crate_name = addr2line
+            .as_ref()
+            .and_then(|addr2line| function_file(addr2line, address))
+            .and_then(|file| {
+                dep::Path::from_std_path(Path::new(&file))
+                    .crate_name()
+                    .map(str::to_string)
+            })
+            .unwrap_or_else(|| crate_of_symbol(&demangle(name)).to_string())
+            // the directories of crates.io dependencies are named with dashes
+            .replace('-', "_");
+
+        *sizes.entry(crate_name).or_insert(0) += symbol.size();
+    }
+
+    let mut sizes = sizes.into_iter().collect::<Vec<_>>();
+    sizes.sort_by(|(a_name, a_size), (b_name, b_size)| {
+        b_size.cmp(a_size).then_with(|| a_name.cmp(b_name))
+    });
+    sizes
+}
+
+/// Returns the source file of the function starting at `address`
+fn function_file(addr2line: &A2lContext, address: u32) -> Option<String> {
+    let frames: Vec<_> = addr2line.find_frames(address as u64).ok()?.collect().ok()?;
+
+    // the location of the outermost frame is the one in the function itself, not in code inlined
+    // into it
+    let file = frames.last()?.location.as_ref()?.file?;
+    Some(file.to_string())
+}
+
+/// Returns the crate a demangled function name starts with, e.g. `defmt_rtt` for
+/// `<defmt_rtt::Logger as defmt::Write>::write`
+fn crate_of_symbol(name: &str) -> &str {
+    let name = name.trim_start_matches(|c| c == '<' || c == '&');
+    let name = name.strip_prefix("mut ").unwrap_or(name);
+
+    match name.split_once("::") {
+        Some((crate_name, _)) => crate_name,
+        None => "<unknown>",
+    }
+}
+
 /// Processed frame
 #[derive(Debug)]
 pub(crate) enum Frame {
@@ -54,6 +167,13 @@ pub(crate) enum Frame {
 pub(crate) struct Subroutine {
     pub(crate) name_or_pc: Either<String, u32>,
     pub(crate) location: Option<Location>,
+    /// Number of subroutines this one is (transitively) inlined into; `0` if not inlined
+    pub(crate) inline_depth: usize,
+    /// Addresses of the function's machine code, according to the symbol table; `None` for
+    /// inlined subroutines
+    pub(crate) range: Option<Range<u32>>,
+    /// Names of the parameter types; only read for `--backtrace-signatures`
+    pub(crate) parameters: Option<Vec<String>>,
 }
 
 type A2lContext = addr2line::Context<EndianReader<RunTimeEndian, Rc<[u8]>>>;
@@ -61,26 +181,45 @@ type A2lContext = addr2line::Context<EndianReader<RunTimeEndian, Rc<[u8]>>>;
 impl Subroutine {
     fn from_pc(
         pc: u32,
+        is_return_address: bool,
         addr2line: Option<&A2lContext>,
         live_functions: &HashSet<&str>,
         current_dir: &Path,
-        symtab: &SymbolMap<SymbolMapName>,
+        symtab: &Symtab,
     ) -> Vec<Subroutine> {
         addr2line
             .and_then(|addr2line| {
-                Self::from_debuginfo(pc, addr2line, live_functions, current_dir, symtab)
+                Self::from_debuginfo(
+                    pc,
+                    is_return_address,
+                    addr2line,
+                    live_functions,
+                    current_dir,
+                    symtab,
+                )
             })
-            .unwrap_or_else(|| vec![Self::from_symtab(pc, symtab)])
+            .unwrap_or_else(|| vec![Self::from_symtab(pc, is_return_address, symtab)])
     }
 
     fn from_debuginfo(
         pc: u32,
+        is_return_address: bool,
         addr2line: &A2lContext,
         live_functions: &HashSet<&str>,
         current_dir: &Path,
-        symtab: &SymbolMap<SymbolMapName>,
+        symtab: &Symtab,
     ) -> Option<Vec<Subroutine>> {
-        let frames: Vec<_> = addr2line.find_frames(pc as u64).ok()?.collect().ok()?;
+        let address = lookup_address(pc, is_return_address);
+        let frames: Vec<_> = match addr2line
+            .find_frames(address as u64)
+            .and_then(|frames| frames.collect())
+        {
+            Ok(frames) => frames,
+            Err(e) => {
+                log::debug!("failed to read the debug info for PC {:#010x}: {}", pc, e);
+                return None;
+            }
+        };
 
         let top_subroutine = frames.last();
 
@@ -98,7 +237,7 @@ impl Subroutine {
 
         let mut subroutines = vec![];
 
-        for frame in &frames {
+        for (index, frame) in frames.iter().enumerate() {
             let demangled_name = frame
                 .function
                 .as_ref()
@@ -109,7 +248,7 @@ impl Subroutine {
             // frames with the same PC
             let name_or_pc = demangled_name
                 .map(Either::Left)
-                .unwrap_or_else(|| name_from_symtab(pc, symtab));
+                .unwrap_or_else(|| name_from_symtab(pc, is_return_address, symtab));
 
             let location = if let Some((file, line, column)) =
                 frame.location.as_ref().and_then(|loc| {
@@ -128,46 +267,425 @@ impl Subroutine {
                     path_is_relative: is_local,
                     line,
                     path: path.to_owned(),
+                    is_declaration: false,
                 })
             } else {
                 None
             };
 
+            // `frames` goes from the innermost inlined subroutine to the outermost function
+            let inline_depth = frames.len() - 1 - index;
+            let range = if inline_depth == 0 {
+                symtab.range(pc, is_return_address)
+            } else {
+                None
+            };
+
             subroutines.push(Subroutine {
                 name_or_pc,
                 location,
+                inline_depth,
+                range,
+                parameters: None,
             })
         }
 
         Some(subroutines)
     }
 
-    fn from_symtab(pc: u32, symtab: &SymbolMap<SymbolMapName>) -> Subroutine {
+    fn from_symtab(pc: u32, is_return_address: bool, symtab: &Symtab) -> Subroutine {
         Subroutine {
-            name_or_pc: name_from_symtab(pc, symtab),
+            name_or_pc: name_from_symtab(pc, is_return_address, symtab),
             location: None,
+            inline_depth: 0,
+            range: symtab.range(pc, is_return_address),
+            parameters: None,
         }
     }
 }
 
-fn name_from_symtab(pc: u32, symtab: &SymbolMap<SymbolMapName>) -> Either<String, u32> {
+/// Returns the address to look up in the debug info for a frame's `pc`
+///
+/// A return address points to the instruction *after* the call, which may already belong to the
+/// next line or (inlined) subroutine; step back into the call instruction instead. The innermost
+/// frame and frames interrupted by an exception are looked up with their exact `pc`.
+fn lookup_address(pc: u32, is_return_address: bool) -> u32 {
+    if is_return_address {
+        // a corrupted stack can yield any return address, including 0
+        pc.wrapping_sub(1)
+    } else {
+        pc
+    }
+}
+
+fn name_from_symtab(pc: u32, is_return_address: bool, symtab: &Symtab) -> Either<String, u32> {
     // the .symtab appears to use address ranges that have their thumb bits set (e.g.
     // `0x101..0x200`). Passing the `pc` with the thumb bit cleared (e.g. `0x100`) to the
     // lookup function sometimes returns the *previous* symbol. Work around the issue by
     // setting `pc`'s thumb bit before looking it up
-    let address = cortexm::set_thumb_bit(pc) as u64;
+    let address = cortexm::set_thumb_bit(lookup_address(pc, is_return_address)) as u64;
 
     symtab
+        .map
         .get(address)
-        .map(|symbol| symbol.name().to_owned())
+        .map(|symbol| {
+            let offset = address - symbol.address();
+            let size = symtab.sizes.get(&symbol.address()).copied().unwrap_or(0);
+
+            // `address` lies past the end of the closest preceding function, e.g. in padding
+            let name = demangle(symbol.name());
+            if size != 0 && offset >= size {
+                format!("<unknown, near: {}+{:#x}>", name, offset)
+            } else {
+                name
+            }
+        })
         .map(Either::Left)
         .unwrap_or(Either::Right(pc))
 }
 
+/// Demangles a Rust symbol name, without its hash
+///
+/// `v0` symbols keep their generic parameters (e.g. `foo::<u8>`), so that different instances of a
+/// generic function can be told apart; only the crate disambiguators are left out.
+///
+/// Any other name, e.g. that of a C or assembly function, is returned unchanged
+fn demangle(name: &str) -> String {
+    match rustc_demangle::try_demangle(name) {
+        Ok(demangled) => format!("{:#}", demangled),
+        Err(_) => name.to_owned(),
+    }
+}
+
+/// The ELF symbol table plus the size of each function, keyed by its address
+struct Symtab<'elf> {
+    map: SymbolMap<SymbolMapName<'elf>>,
+    sizes: HashMap<u64, u64>,
+}
+
+impl<'elf> Symtab<'elf> {
+    fn new(elf: &'elf Elf) -> Self {
+        let sizes = elf
+            .symbols()
+            .filter(|symbol| symbol.kind() == SymbolKind::Text)
+            .map(|symbol| (symbol.address(), symbol.size()))
+            .collect();
+
+        Self {
+            map: elf.symbol_map(),
+            sizes,
+        }
+    }
+
+    /// Returns the address range of the function containing `pc`, if its size is known
+    fn range(&self, pc: u32, is_return_address: bool) -> Option<Range<u32>> {
+        // see `name_from_symtab` for why the thumb bit is set
+        let address = cortexm::set_thumb_bit(lookup_address(pc, is_return_address)) as u64;
+        let symbol = self.map.get(address)?;
+        let size = *self.sizes.get(&symbol.address())?;
+        if address - symbol.address() >= size {
+            return None;
+        }
+
+        let start = cortexm::clear_thumb_bit(symbol.address() as u32);
+        Some(start..start.checked_add(size as u32)?)
+    }
+
+    /// Returns the address range and name of each function overlapping `range`, ordered by address
+    ///
+    /// Functions of unknown size are left out
+    fn functions_in_range(&self, range: Range<u32>) -> Vec<(Range<u32>, &'elf str)> {
+        // `symbols` are sorted by address
+        self.map
+            .symbols()
+            .iter()
+            .filter_map(|symbol| {
+                let size = *self.sizes.get(&symbol.address())?;
+                let start = cortexm::clear_thumb_bit(symbol.address() as u32);
+                // a bogus size would reach past the end of the address space
+                let end = start.checked_add(size as u32)?;
+                if start < range.end && range.start < end {
+                    Some((start..end, symbol.name()))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct Location {
     pub(crate) column: Option<u32>,
     pub(crate) path_is_relative: bool,
     pub(crate) line: u32,
     pub(crate) path: PathBuf,
+    /// The line is where the function is declared, not the code that was executing; used when
+    /// there is no line information for the PC
+    pub(crate) is_declaration: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use super::*;
+
+    #[test]
+    fn zero_return_address_does_not_underflow() {
+        assert_eq!(0xffff_ffff, lookup_address(0, true));
+        assert_eq!(0, lookup_address(0, false));
+    }
+
+    #[test]
+    fn crate_of_symbol_is_the_first_path_segment() {
+        assert_eq!(
+            "cortex_m_rt",
+            crate_of_symbol("cortex_m_rt::DefaultHandler_")
+        );
+        assert_eq!(
+            "defmt_rtt",
+            crate_of_symbol("<defmt_rtt::Logger as defmt::Write>::write")
+        );
+        assert_eq!(
+            "app",
+            crate_of_symbol("<&mut app::Uart as core::fmt::Write>::write_str")
+        );
+        assert_eq!("<unknown>", crate_of_symbol("Reset"));
+    }
+
+    #[test]
+    fn crate_sizes_cover_all_live_functions() {
+        let elf_bytes = include_bytes!("../../tests/test_elfs/panic");
+        let elf = Elf::parse(elf_bytes).unwrap();
+
+        let sizes = crate_sizes(&elf);
+        let crates = sizes.iter().map(|(name, _)| &**name).collect::<Vec<_>>();
+        assert!(crates.contains(&"defmt_rtt"), "{:?}", crates);
+        assert!(crates.contains(&"panic"), "{:?}", crates);
+
+        let sizes_are_sorted = sizes.windows(2).all(|pair| pair[0].1 >= pair[1].1);
+        assert!(sizes_are_sorted);
+    }
+
+    #[test]
+    fn non_rust_symbols_are_not_demangled() {
+        assert_eq!("__aeabi_memcpy4", demangle("__aeabi_memcpy4"));
+        assert_eq!("_start", demangle("_start"));
+    }
+
+    #[test]
+    fn legacy_rust_symbols_are_demangled_without_hash() {
+        assert_eq!("app::main", demangle("_ZN3app4main17h0123456789abcdefE"));
+    }
+
+    #[test]
+    fn v0_rust_symbols_keep_their_generic_parameters() {
+        assert_eq!("app::foo::<u8>", demangle("_RINvCs1234_3app3foohE"));
+        assert_eq!("app::foo::<u32>", demangle("_RINvCs1234_3app3foomE"));
+    }
+
+    #[test]
+    fn v0_rust_symbols_keep_nested_generic_parameters() {
+        assert_eq!(
+            "app::foo::<core::option::Option<u8>>",
+            demangle("_RINvCs1234_3app3fooINtNtCs5678_4core6option6OptionhEE")
+        );
+    }
+
+    #[test]
+    fn names_shorter_than_a_hash_are_demangled() {
+        assert_eq!("a::b", demangle("_ZN1a1bE"));
+    }
+
+    #[test]
+    fn v0_rust_symbols_with_multibyte_names_are_demangled() {
+        assert_eq!("app::grüße", demangle("_RNvCscAEuU5ehQ2G_3appu9gre_6ka8l"));
+        assert_eq!(
+            "app::grüße::<u8>",
+            demangle("_RINvCscAEuU5ehQ2G_3appu9gre_6ka8lhEB2_")
+        );
+    }
+
+    /// Symbolicates each of `pcs` in the `panic` test ELF into `(name, inline_depth)` pairs
+    fn fixture_frames(pcs: &[u32]) -> Vec<Vec<(String, usize)>> {
+        let elf_bytes = include_bytes!("../../tests/test_elfs/panic");
+        let elf = Elf::parse(elf_bytes).unwrap();
+
+        pcs.iter()
+            .map(|&pc| {
+                let raw_frames = [RawFrame::Subroutine {
+                    pc,
+                    is_return_address: false,
+                }];
+                frames(&raw_frames, Path::new("/"), &elf)
+                    .into_iter()
+                    .map(|frame| match frame {
+                        Frame::Subroutine(subroutine) => (
+                            subroutine.name_or_pc.left().unwrap(),
+                            subroutine.inline_depth,
+                        ),
+                        Frame::Exception => unreachable!("only subroutines were symbolicated"),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fixture_function_entries_resolve_to_their_functions() {
+        let golden = vec![
+            vec![("Reset".to_string(), 0)],
+            vec![("main".to_string(), 0)],
+            vec![("panic::__cortex_m_rt_main".to_string(), 0)],
+            vec![("defmt::export::panic".to_string(), 0)],
+            vec![("cortex_m::asm::udf".to_string(), 0)],
+            vec![("_defmt_panic".to_string(), 0)],
+        ];
+
+        assert_eq!(
+            golden,
+            fixture_frames(&[
+                0x0000_0100,
+                0x0000_018c,
+                0x0000_0196,
+                0x0000_05bc,
+                0x0000_06f6,
+                0x0000_0700,
+            ])
+        );
+    }
+
+    /// The `panic` ELF links `cortex-m`'s precompiled assembly archive, whose units were produced
+    /// by an older rustc and have their own abbreviation tables
+    #[test]
+    fn units_from_different_producers_all_contribute_frames() {
+        let elf_bytes = include_bytes!("../../tests/test_elfs/panic");
+        let elf = Elf::parse(elf_bytes).unwrap();
+
+        // `panic::__cortex_m_rt_main` (rustc 1.52.1) and `__udf` (rustc 1.47.0 archive)
+        for &(pc, function, file) in &[
+            (0x0000_0196, "panic::__cortex_m_rt_main", "panic.rs"),
+            (0x0000_071c, "__udf", "lib.rs"),
+        ] {
+            let raw_frames = [RawFrame::Subroutine {
+                pc,
+                is_return_address: false,
+            }];
+            let frames = frames(&raw_frames, Path::new("/"), &elf);
+            let outermost = match frames.last() {
+                Some(Frame::Subroutine(subroutine)) => subroutine,
+                _ => panic!("no subroutine frame at {:#010x}", pc),
+            };
+
+            assert_eq!(
+                Some(function),
+                outermost.name_or_pc.as_ref().left().map(String::as_str)
+            );
+            let location = outermost.location.as_ref().expect("no location info");
+            assert_eq!(Some(OsStr::new(file)), location.path.file_name());
+        }
+    }
+
+    #[test]
+    fn exact_pc_is_looked_up_unchanged() {
+        assert_eq!(0x0000_1234, lookup_address(0x0000_1234, false));
+    }
+
+    #[test]
+    fn return_address_is_looked_up_in_the_call_instruction() {
+        // `bl` at 0x1230..0x1234; the return address is the following instruction
+        assert_eq!(0x0000_1233, lookup_address(0x0000_1234, true));
+    }
+
+    #[test]
+    fn return_address_at_end_of_function_resolves_to_caller() {
+        // `caller` ends with a call to a diverging function, so its return address is the first
+        // instruction of `next`
+        let symtab = Symtab {
+            map: SymbolMap::new(vec![
+                SymbolMapName::new(0x0000_0101, "caller"),
+                SymbolMapName::new(0x0000_0111, "next"),
+            ]),
+            sizes: HashMap::new(),
+        };
+
+        assert_eq!(
+            Either::Left("caller".to_string()),
+            name_from_symtab(0x0000_0110, true, &symtab)
+        );
+        assert_eq!(
+            Either::Left("next".to_string()),
+            name_from_symtab(0x0000_0110, false, &symtab)
+        );
+    }
+
+    #[test]
+    fn address_past_end_of_function_is_a_near_miss() {
+        let symtab = Symtab {
+            map: SymbolMap::new(vec![SymbolMapName::new(0x0000_0101, "function")]),
+            sizes: vec![(0x0000_0101, 0x10)].into_iter().collect(),
+        };
+
+        assert_eq!(
+            Either::Left("function".to_string()),
+            name_from_symtab(0x0000_010E, false, &symtab)
+        );
+        assert_eq!(
+            Either::Left("<unknown, near: function+0x14>".to_string()),
+            name_from_symtab(0x0000_0114, false, &symtab)
+        );
+    }
+
+    #[test]
+    fn functions_overlapping_the_range_are_found_in_order() {
+        let symtab = Symtab {
+            map: SymbolMap::new(vec![
+                SymbolMapName::new(0x0000_0131, "third"),
+                SymbolMapName::new(0x0000_0101, "first"),
+                SymbolMapName::new(0x0000_0111, "second"),
+                SymbolMapName::new(0x0000_0141, "unknown_size"),
+            ]),
+            sizes: vec![
+                (0x0000_0101, 0x10),
+                (0x0000_0111, 0x20),
+                (0x0000_0131, 0x10),
+            ]
+            .into_iter()
+            .collect(),
+        };
+
+        // partially overlaps `first` and `third`
+        assert_eq!(
+            vec![
+                (0x0000_0100..0x0000_0110, "first"),
+                (0x0000_0110..0x0000_0130, "second"),
+                (0x0000_0130..0x0000_0140, "third"),
+            ],
+            symtab.functions_in_range(0x0000_010f..0x0000_0131)
+        );
+        // the range's end is exclusive, just like the functions'
+        assert_eq!(
+            vec![(0x0000_0110..0x0000_0130, "second")],
+            symtab.functions_in_range(0x0000_0110..0x0000_0130)
+        );
+        assert_eq!(
+            Vec::<(Range<u32>, &str)>::new(),
+            symtab.functions_in_range(0x0000_0140..0x0000_0200)
+        );
+    }
+
+    #[test]
+    fn range_covers_the_whole_function() {
+        let symtab = Symtab {
+            map: SymbolMap::new(vec![SymbolMapName::new(0x0000_0101, "function")]),
+            sizes: vec![(0x0000_0101, 0x10)].into_iter().collect(),
+        };
+
+        assert_eq!(
+            Some(0x0000_0100..0x0000_0110),
+            symtab.range(0x0000_010E, false)
+        );
+        assert_eq!(None, symtab.range(0x0000_0114, false));
+    }
 }
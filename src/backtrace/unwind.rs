@@ -1,14 +1,14 @@
 //! unwind target's program
 
-use anyhow::{anyhow, Context as _};
+use anyhow::anyhow;
 use gimli::{BaseAddresses, DebugFrame, UninitializedUnwindContext, UnwindSection as _};
-use probe_rs::{config::RamRegion, Core};
+use probe_rs::config::RamRegion;
 
 use crate::{
     backtrace::Outcome,
     cortexm,
     elf::Elf,
-    registers::{self, Registers},
+    registers::{self, Registers, Target},
     stacked::Stacked,
 };
 
@@ -24,7 +24,15 @@ fn missing_debug_info(pc: u32) -> String {
 ///
 /// This returns as much info as could be collected, even if the collection is interrupted by an error.
 /// If an error occurred during processing, it is stored in `Output::processing_error`.
-pub(crate) fn target(core: &mut Core, elf: &Elf, active_ram_region: &Option<RamRegion>) -> Output {
+///
+/// The PCs of the returned frames are link-time addresses: `load_base` is subtracted from the
+/// addresses the program runs at.
+pub(crate) fn target(
+    target: &mut dyn Target,
+    elf: &Elf,
+    active_ram_region: &Option<RamRegion>,
+    load_base: u32,
+) -> Output {
     let mut output = Output {
         corrupted: true,
         outcome: Outcome::Ok,
@@ -45,12 +53,24 @@ pub(crate) fn target(core: &mut Core, elf: &Elf, active_ram_region: &Option<RamR
         };
     }
 
-    let mut pc = unwrap_or_return_output!(core.read_core_reg(registers::PC));
-    let sp = unwrap_or_return_output!(core.read_core_reg(registers::SP));
-    let lr = unwrap_or_return_output!(core.read_core_reg(registers::LR));
+    let mut pc = link_time_address(
+        unwrap_or_return_output!(target.read_core_reg(registers::PC)),
+        load_base,
+    );
+    // the innermost frame is seeded with the exact PC the core halted at
+    let mut pc_is_return_address = false;
+    let sp = unwrap_or_return_output!(target.read_core_reg(registers::SP));
+    let lr = unwrap_or_return_output!(target.read_core_reg(registers::LR));
+    // a core halted on the first instruction of an exception handler has the interrupted
+    // program's registers stacked at SP and `EXC_RETURN` in LR; that frame can be unwound even if
+    // the handler, e.g. an assembly trampoline, has no unwind info
+    let at_exception_entry = lr >= cortexm::EXC_RETURN_MARKER
+        && target
+            .read_core_reg(registers::XPSR)
+            .map_or(false, cortexm::is_handler_mode);
     let base_addresses = BaseAddresses::default();
     let mut unwind_context = UninitializedUnwindContext::new();
-    let mut registers = Registers::new(lr, sp, core);
+    let mut registers = Registers::new(lr, sp, target);
 
     loop {
         if let Some(outcome) =
@@ -59,23 +79,41 @@ pub(crate) fn target(core: &mut Core, elf: &Elf, active_ram_region: &Option<RamR
             output.outcome = outcome;
         }
 
-        output.raw_frames.push(RawFrame::Subroutine { pc });
+        output.raw_frames.push(RawFrame::Subroutine {
+            pc,
+            is_return_address: pc_is_return_address,
+        });
 
-        let uwt_row = unwrap_or_return_output!(elf
-            .debug_frame
-            .unwind_info_for_address(
-                &base_addresses,
-                &mut unwind_context,
-                pc.into(),
-                DebugFrame::cie_from_offset,
-            )
-            .with_context(|| missing_debug_info(pc)));
+        let uwt_row = elf.debug_frame.unwind_info_for_address(
+            &base_addresses,
+            &mut unwind_context,
+            pc.into(),
+            DebugFrame::cie_from_offset,
+        );
 
-        let cfa_changed = unwrap_or_return_output!(registers.update_cfa(uwt_row.cfa()));
+        let cfa_changed = match uwt_row {
+            Ok(uwt_row) => {
+                let cfa_changed = unwrap_or_return_output!(registers.update_cfa(uwt_row.cfa()));
 
-        for (reg, rule) in uwt_row.registers() {
-            unwrap_or_return_output!(registers.update(reg, rule));
-        }
+                for (reg, rule) in uwt_row.registers() {
+                    unwrap_or_return_output!(registers.update(reg, rule));
+                }
+
+                cfa_changed
+            }
+            // nothing has been pushed yet, so SP and LR still hold the values at exception entry
+            Err(_) if at_exception_entry && output.raw_frames.len() == 1 => {
+                log::debug!(
+                    "no unwind info for exception handler at {:#010x}; reading the stacked frame",
+                    pc
+                );
+                false
+            }
+            Err(err) => {
+                output.processing_error = Some(anyhow!(err).context(missing_debug_info(pc)));
+                return output;
+            }
+        };
 
         let lr = unwrap_or_return_output!(registers.get(registers::LR));
 
@@ -89,7 +127,7 @@ pub(crate) fn target(core: &mut Core, elf: &Elf, active_ram_region: &Option<RamR
         // invalid combinations of final bits 0-4 to prevent futile backtrace re-generation attempts
         let exception_entry = lr >= cortexm::EXC_RETURN_MARKER;
 
-        let program_counter_changed = !cortexm::subroutine_eq(lr, pc);
+        let program_counter_changed = !cortexm::subroutine_eq(link_time_address(lr, load_base), pc);
 
         // If the frame didn't move, and the program counter didn't change, bail out (otherwise we
         // might print the same frame over and over).
@@ -111,7 +149,7 @@ pub(crate) fn target(core: &mut Core, elf: &Elf, active_ram_region: &Option<RamR
                 .map(|ram_region| ram_region.range.clone())
                 .unwrap_or(cortexm::VALID_RAM_ADDRESS);
             let stacked = if let Some(stacked) =
-                unwrap_or_return_output!(Stacked::read(registers.core, sp, fpu, ram_bounds))
+                unwrap_or_return_output!(Stacked::read(registers.target, sp, fpu, ram_bounds))
             {
                 stacked
             } else {
@@ -123,9 +161,12 @@ pub(crate) fn target(core: &mut Core, elf: &Elf, active_ram_region: &Option<RamR
             // adjust the stack pointer for stacked registers
             registers.insert(registers::SP, sp + stacked.size());
 
-            pc = stacked.pc;
+            // the stacked PC points at the interrupted instruction itself
+            pc = link_time_address(stacked.pc, load_base);
+            pc_is_return_address = false;
         } else if cortexm::is_thumb_bit_set(lr) {
-            pc = cortexm::clear_thumb_bit(lr);
+            pc = cortexm::clear_thumb_bit(link_time_address(lr, load_base));
+            pc_is_return_address = true;
         } else {
             output.processing_error = Some(anyhow!(
                 "bug? LR ({:#010x}) didn't have the Thumb bit set",
@@ -138,6 +179,13 @@ pub(crate) fn target(core: &mut Core, elf: &Elf, active_ram_region: &Option<RamR
     output
 }
 
+/// Maps an address of the running program back to the one it was linked at
+///
+/// Addresses below `load_base` wrap around and resolve to nothing.
+pub(crate) fn link_time_address(address: u32, load_base: u32) -> u32 {
+    address.wrapping_sub(load_base)
+}
+
 fn check_hard_fault(
     pc: u32,
     vector_table: &cortexm::VectorTable,
@@ -173,7 +221,11 @@ pub struct Output {
 /// Backtrace frame prior to 'symbolication'
 #[derive(Debug)]
 pub(crate) enum RawFrame {
-    Subroutine { pc: u32 },
+    Subroutine {
+        pc: u32,
+        /// `pc` was taken from the Link Register and points one instruction past the call
+        is_return_address: bool,
+    },
     Exception,
 }
 
@@ -195,3 +247,23 @@ fn overflowed_stack(sp: u32, active_ram_region: &Option<RamRegion>) -> bool {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_base_is_subtracted() {
+        assert_eq!(0x0000_0400, link_time_address(0x2000_0400, 0x2000_0000));
+    }
+
+    #[test]
+    fn load_base_keeps_the_thumb_bit() {
+        assert_eq!(0x0000_0401, link_time_address(0x0800_8401, 0x0800_8000));
+    }
+
+    #[test]
+    fn zero_load_base_is_the_identity() {
+        assert_eq!(0x0800_1234, link_time_address(0x0800_1234, 0));
+    }
+}
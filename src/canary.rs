@@ -1,4 +1,3 @@
-use crate::TIMEOUT;
 use crate::{Elf, TargetInfo};
 use probe_rs::{MemoryInterface, Session};
 
@@ -31,8 +30,9 @@ const CANARY_VALUE: u8 = 0xAA;
 /// The canary size is 10% of the available stack space or 1 KiB, whichever is smallest.
 ///
 /// When the programs ends (due to panic or breakpoint) the integrity of the canary is checked. If it was
-/// "touched" (any of its bytes != `CANARY_VALUE`) then that is considered to be a *potential* stack
-/// overflow.
+/// "touched" (any of its bytes != `CANARY_VALUE`) within `threshold` bytes of its bottom then that is
+/// considered to be a *potential* stack overflow. Touches further up, e.g. by an interrupt handler
+/// that ran at peak stack usage, are only reported.
 ///
 /// The canary is not installed if the program memory layout is "inverted" (stack is *below* the
 /// static variables).
@@ -49,7 +49,6 @@ impl Canary {
         elf: &Elf,
     ) -> Result<Option<Self>, anyhow::Error> {
         let mut core = sess.core(0)?;
-        core.reset_and_halt(TIMEOUT)?;
 
         // Decide if and where to place the stack canary.
         if let Some(highest_static_var_address) = target_info.highest_static_var_address {
@@ -85,24 +84,109 @@ impl Canary {
         Ok(None)
     }
 
-    pub(crate) fn touched(self, core: &mut probe_rs::Core, elf: &Elf) -> anyhow::Result<bool> {
+    /// Returns `true` if the program touched the canary within `threshold` bytes of its bottom
+    pub(crate) fn overflowed(
+        self,
+        core: &mut probe_rs::Core,
+        elf: &Elf,
+        threshold: u32,
+    ) -> anyhow::Result<bool> {
         let mut canary = vec![0; self.size];
         core.read_8(self.address, &mut canary)?;
 
-        if let Some(pos) = canary.iter().position(|b| *b != CANARY_VALUE) {
-            let touched_address = self.address + pos as u32;
-            log::debug!("canary was touched at {:#010X}", touched_address);
+        if let Some(disturbance) = Disturbance::find(&canary) {
+            let touched_address = self.address + disturbance.lowest_offset as u32;
+            log::debug!(
+                "canary was touched at {:#010X}; {} of its {} bytes were disturbed",
+                touched_address,
+                disturbance.num_bytes,
+                self.size
+            );
 
             let min_stack_usage = elf.vector_table.initial_stack_pointer - touched_address;
-            log::warn!(
-                "program has used at least {} bytes of stack space, data segments \
+            if disturbance.reaches_bottom(threshold) {
+                log::warn!(
+                    "program has used at least {} bytes of stack space, data segments \
                      may be corrupted due to stack overflow",
-                min_stack_usage,
-            );
-            Ok(true)
+                    min_stack_usage,
+                );
+                Ok(true)
+            } else {
+                log::info!(
+                    "program has used at least {} bytes of stack space, coming within {} bytes \
+                     of the static variables; not treating this as a stack overflow",
+                    min_stack_usage,
+                    disturbance.lowest_offset,
+                );
+                Ok(false)
+            }
         } else {
             log::debug!("stack canary intact");
             Ok(false)
         }
     }
 }
+
+/// The part of the canary the program overwrote
+#[derive(Debug, PartialEq)]
+struct Disturbance {
+    /// Number of bytes that no longer hold `CANARY_VALUE`
+    num_bytes: usize,
+    /// Offset of the lowest such byte from the bottom of the canary
+    lowest_offset: usize,
+}
+
+impl Disturbance {
+    fn find(canary: &[u8]) -> Option<Self> {
+        let lowest_offset = canary.iter().position(|b| *b != CANARY_VALUE)?;
+        let num_bytes = canary.iter().filter(|b| **b != CANARY_VALUE).count();
+
+        Some(Self {
+            num_bytes,
+            lowest_offset,
+        })
+    }
+
+    /// Returns `true` if the disturbance lies within `threshold` bytes of the canary's bottom
+    fn reaches_bottom(&self, threshold: u32) -> bool {
+        self.lowest_offset < threshold as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intact_canary_is_not_disturbed() {
+        assert_eq!(None, Disturbance::find(&[CANARY_VALUE; 8]));
+    }
+
+    #[test]
+    fn disturbance_counts_bytes_and_finds_the_lowest() {
+        let mut canary = [CANARY_VALUE; 8];
+        canary[3] = 0;
+        canary[5] = 0;
+        canary[7] = 0;
+
+        assert_eq!(
+            Some(Disturbance {
+                num_bytes: 3,
+                lowest_offset: 3
+            }),
+            Disturbance::find(&canary)
+        );
+    }
+
+    #[test]
+    fn only_disturbance_within_threshold_reaches_bottom() {
+        let disturbance = Disturbance {
+            num_bytes: 1,
+            lowest_offset: 3,
+        };
+
+        assert!(!disturbance.reaches_bottom(1));
+        assert!(!disturbance.reaches_bottom(3));
+        assert!(disturbance.reaches_bottom(4));
+    }
+}
@@ -1,12 +1,27 @@
-use std::path::PathBuf;
+use std::{
+    env,
+    num::{NonZeroU32, ParseIntError},
+    ops::Range,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
+use anyhow::{anyhow, bail, Context as _};
 use defmt_decoder::DEFMT_VERSION;
 use git_version::git_version;
 use log::Level;
-use probe_rs::Probe;
+use probe_rs::{Probe, WireProtocol};
+use regex::Regex;
 use structopt::{clap::AppSettings, StructOpt};
 
-use crate::probe;
+use crate::{
+    backtrace::{BacktraceOptions, BacktraceStyle, FrameNumbers},
+    dep::PathRules,
+    dump::SymbolRead,
+    log_format::{LogFormat, TimestampFormat},
+    probe, query,
+    semihosting::EnvVar,
+};
 
 /// Successfull termination of process.
 const EXIT_SUCCESS: i32 = 0;
@@ -23,10 +38,19 @@ pub(crate) struct Opts {
     #[structopt(long)]
     list_probes: bool,
 
+    /// Describe the connected probes and, with `--chip`, the chip's memory map in the given
+    /// format, then exit. Only `json` is supported.
+    #[structopt(long, possible_values(&["json"]))]
+    query: Option<String>,
+
     /// The chip to program.
-    #[structopt(long, required_unless_one(&["list-chips", "list-probes", "version"]), env = "PROBE_RUN_CHIP")]
+    #[structopt(long, required_unless_one(&["list-chips", "list-probes", "query", "version", "dry-run", "decode-rtt-file", "symbolize-addr", "functions-in-range", "crate-sizes", "dump-line-program", "core-dump"]), env = "PROBE_RUN_CHIP")]
     chip: Option<String>,
 
+    /// Path to a probe-rs target description (YAML) of a chip not in the built-in registry.
+    #[structopt(long, parse(from_os_str))]
+    chip_description: Option<PathBuf>,
+
     /// The probe to use (eg. `VID:PID`, `VID:PID:Serial`, or just `Serial`).
     #[structopt(long, env = "PROBE_RUN_PROBE")]
     pub(crate) probe: Option<String>,
@@ -35,56 +59,403 @@ pub(crate) struct Opts {
     #[structopt(long)]
     pub(crate) speed: Option<u32>,
 
-    /// Path to an ELF firmware file.
-    #[structopt(name = "ELF", parse(from_os_str), required_unless_one(&["list-chips", "list-probes", "version"]))]
+    /// The wire protocol to connect to the target with: `swd` or `jtag`. Defaults to SWD.
+    #[structopt(long)]
+    pub(crate) protocol: Option<WireProtocol>,
+
+    /// Path to an ELF firmware file or a UF2 image.
+    #[structopt(name = "ELF", parse(from_os_str), required_unless_one(&["list-chips", "list-probes", "query", "version"]))]
     elf: Option<PathBuf>,
 
+    /// ELF file with the debug info of the flashed UF2 image.
+    #[structopt(long, parse(from_os_str))]
+    pub(crate) debug_file: Option<PathBuf>,
+
     /// Skip writing the application binary to flash.
     #[structopt(long, conflicts_with = "defmt")]
     pub(crate) no_flash: bool,
 
+    /// Flash the program and exit without running it.
+    #[structopt(long, conflicts_with = "no-flash")]
+    pub(crate) flash_only: bool,
+
+    /// If flashing fails because the chip is read-out protected (locked), mass-erase the chip and
+    /// try again. This wipes the whole flash! Locking is detected on STM32 and nRF52 chips.
+    #[structopt(long)]
+    pub(crate) chip_erase_if_locked: bool,
+
+    /// Don't report the progress of erasing and programming the flash.
+    #[structopt(long)]
+    pub(crate) no_progress: bool,
+
+    /// Flash the program this many bytes after its link-time load addresses, e.g. to place it
+    /// behind a bootloader.
+    #[structopt(long, parse(try_from_str = parse_address))]
+    pub(crate) flash_offset: Option<u32>,
+
+    /// Address the program is loaded at relative to its link-time addresses, for position
+    /// independent firmware. PCs are shifted back by this much before symbolication.
+    #[structopt(long, parse(try_from_str = parse_address), default_value = "0")]
+    pub(crate) load_base: u32,
+
+    /// Shell command to run before connecting to the probe; `probe-run` aborts if it fails.
+    #[structopt(long)]
+    pub(crate) before_run: Option<String>,
+
+    /// Shell command to run once the program stopped, also if `probe-run` failed. Its environment
+    /// contains `PROBE_RUN_EXIT_REASON` (e.g. `ok`, `panic` or `error`), `PROBE_RUN_EXIT_CODE` and,
+    /// on errors, `PROBE_RUN_ERROR`.
+    #[structopt(long)]
+    pub(crate) after_run: Option<String>,
+
     /// Connect to device when NRST is pressed.
     #[structopt(long)]
     pub(crate) connect_under_reset: bool,
 
+    /// Freeze the independent and window watchdogs while the core is halted, so that they don't
+    /// reset it during a debugging session. Supported on the common STM32 families.
+    #[structopt(long)]
+    pub(crate) disable_watchdog: bool,
+
+    /// Time in milliseconds to wait for the target to initialize RTT before halting it and failing.
+    #[structopt(long, default_value = "3000")]
+    pub(crate) rtt_attach_timeout: u64,
+
+    /// Run the program until it has set up RTT, print its RTT channels, then halt it and exit.
+    #[structopt(long)]
+    pub(crate) list_rtt_channels: bool,
+
+    /// Abort with exit code 125 if erasing and programming the flash make no progress for this many
+    /// seconds. `--after-run` still runs, but the probe session is not torn down.
+    #[structopt(long)]
+    pub(crate) flash_timeout: Option<u64>,
+
+    /// Abort with exit code 124 if attaching, flashing, running and tearing down together take
+    /// longer than this many seconds. `--after-run` still runs, but the probe session is not torn
+    /// down.
+    #[structopt(long)]
+    pub(crate) global_timeout: Option<u64>,
+
+    /// RTT up channel to print the logs of, or `all` to print every up channel, each line prefixed
+    /// with the channel's number and name.
+    #[structopt(long, default_value = "0")]
+    pub(crate) rtt_up_channels: RttUpChannels,
+
+    /// Mode the RTT up channels are expected in: `blocking` switches channel 0 to blocking before
+    /// `main` runs, `non-blocking` keeps the program's own configuration. A mismatch is warned
+    /// about.
+    #[structopt(long, default_value = "blocking")]
+    pub(crate) rtt_mode: RttMode,
+
+    /// Send the lines typed on stdin to this RTT down channel, e.g. `0`. Without it, stdin is not
+    /// read.
+    #[structopt(long)]
+    pub(crate) rtt_down_channel: Option<usize>,
+
+    /// How to reset the device before running the program: `hard` (through the nRST pin), `soft`
+    /// or `none` (only halt the running program).
+    #[structopt(long, default_value = "soft")]
+    pub(crate) pre_run_reset: PreRunReset,
+
+    /// Don't reset the device on exit if the program panicked, faulted or timed out; leave it
+    /// halted for a debugger to attach.
+    #[structopt(long)]
+    pub(crate) leave_halted: bool,
+
+    /// Only report a stack overflow if the program touched the stack canary within this many bytes
+    /// of its bottom, next to the static variables.
+    #[structopt(long, default_value = "1")]
+    pub(crate) canary_threshold: u32,
+
     /// Enable more verbose logging.
     #[structopt(short, long, parse(from_occurrences))]
     verbose: u32,
 
+    /// Only log errors from `probe-run` itself; the target's logs are still printed.
+    #[structopt(short, long, conflicts_with = "verbose")]
+    pub(crate) quiet: bool,
+
     /// Prints version information
     #[structopt(short = "V", long)]
     version: bool,
 
-    /// Print a backtrace even if the program ran successfully
+    /// Print a backtrace even if the program ran successfully (deprecated; use
+    /// `--backtrace=always`)
     #[structopt(long)]
     pub(crate) force_backtrace: bool,
 
+    /// When to print a backtrace: `auto` (on abnormal termination), `always` or `never`
+    #[structopt(long, default_value = "auto")]
+    pub(crate) backtrace: BacktraceOptions,
+
     /// Configure the number of lines to print before a backtrace gets cut off
     #[structopt(long, default_value = "50")]
     pub(crate) max_backtrace_len: u32,
 
+    /// Print the backtrace from the outermost frame to the innermost one
+    #[structopt(long)]
+    pub(crate) backtrace_reverse: bool,
+
+    /// Leave `compiler_builtins` frames (e.g. integer division helpers) out of the backtrace
+    #[structopt(long)]
+    pub(crate) backtrace_hide_builtins: bool,
+
+    /// Only show backtrace frames from the local workspace, leaving out std, dependencies and
+    /// compiler builtins. The innermost frame is kept if no frame is local.
+    #[structopt(long)]
+    pub(crate) backtrace_self_only: bool,
+
+    /// Always show the backtrace frames of functions whose name matches this regular expression.
+    /// This takes precedence over `--backtrace-hide-builtins` and `--backtrace-self-only`.
+    #[structopt(long)]
+    pub(crate) backtrace_keep: Option<Regex>,
+
+    /// Configure the number of inlined frames to print per address before they get cut off
+    #[structopt(long, default_value = "20")]
+    pub(crate) max_inline_frames: u32,
+
+    /// Leave the innermost N frames (e.g. the panic machinery) out of the backtrace
+    #[structopt(long, default_value = "0")]
+    pub(crate) backtrace_skip: u32,
+
+    /// Number the frames shown after `--backtrace-skip` as if none had been left out
+    #[structopt(long)]
+    pub(crate) backtrace_keep_numbers: bool,
+
+    /// Number of the innermost printed frame: `from-zero` (like GDB) or `from-one`. JSON events
+    /// always carry the 0-based index.
+    #[structopt(long, default_value = "from-zero")]
+    pub(crate) backtrace_frame_numbers: FrameNumbers,
+
+    /// Append the address range of each function (from the symbol table) to its frame
+    #[structopt(long)]
+    pub(crate) backtrace_ranges: bool,
+
+    /// Append the parameter types from the debug info to function names, e.g. `foo(u8, &Bar)`.
+    /// This reads all of the debug info, so it slows down printing the backtrace.
+    #[structopt(long)]
+    pub(crate) backtrace_signatures: bool,
+
+    /// After printing a backtrace, open the innermost frame located in a local source file with
+    /// `$VISUAL` or `$EDITOR`
+    #[structopt(long)]
+    pub(crate) open_editor: bool,
+
+    /// If the program faulted, disassemble the instructions around the faulting one (requires the
+    /// `disasm` feature)
+    #[structopt(long)]
+    pub(crate) disasm_on_fault: bool,
+
+    /// How to lay out inlined frames: `flat` or `nested` (indented below their caller).
+    #[structopt(long, default_value = "flat")]
+    pub(crate) backtrace_style: BacktraceStyle,
+
     /// Whether to shorten paths (e.g. to crates.io dependencies) in backtraces and defmt logs
     #[structopt(long)]
     pub(crate) shorten_paths: bool,
 
+    /// Print paths in backtraces and defmt logs exactly as found in the debug info
+    #[structopt(long, conflicts_with = "shorten-paths")]
+    pub(crate) no_shorten_paths: bool,
+
+    /// Per-crate overrides of `--shorten-paths`, from the `[paths]` section of `.probe-run.toml`
+    #[structopt(skip)]
+    pub(crate) path_rules: PathRules,
+
+    /// Annotate backtrace frames with their inline depth; meant for `probe-run` bug reports
+    #[structopt(long, hidden = true)]
+    pub(crate) backtrace_debug: bool,
+
+    /// Parse and symbolicate the ELF file without connecting to a probe, then exit.
+    #[structopt(long)]
+    dry_run: bool,
+
+    /// Print the subroutines and source locations at the given address, then exit. May be repeated.
+    #[structopt(long, parse(try_from_str = parse_address), number_of_values = 1)]
+    symbolize_addr: Vec<u32>,
+
+    /// List the functions whose machine code overlaps the address range `START..END`, e.g. a
+    /// flash sector, then exit.
+    #[structopt(long, parse(try_from_str = parse_address_range))]
+    functions_in_range: Option<Range<u32>>,
+
+    /// Print the DWARF line program rows around the given address, as probe-run reads them to map
+    /// addresses to source lines, then exit.
+    #[structopt(long, parse(try_from_str = parse_address))]
+    dump_line_program: Option<u32>,
+
+    /// Print how many bytes of machine code each crate contributes to the program, then exit.
+    #[structopt(long)]
+    crate_sizes: bool,
+
+    /// Decode raw RTT data captured into a file, using the ELF file's defmt info, then exit.
+    #[structopt(long, parse(from_os_str))]
+    decode_rtt_file: Option<PathBuf>,
+
+    /// Print the backtrace of a core dump (PC, LR, SP and stack memory) saved by the program,
+    /// then exit. See `src/core_dump.rs` for the file format.
+    #[structopt(long, parse(from_os_str))]
+    core_dump: Option<PathBuf>,
+
+    /// Report how many functions are in the symbol table, live, and covered by debug info.
+    #[structopt(long)]
+    pub(crate) symbol_stats: bool,
+
+    /// Layout of defmt log lines, e.g. "{timestamp} {level} {file}:{line} {message}".
+    /// Available placeholders: timestamp, level, file, line, module and message.
+    #[structopt(long)]
+    pub(crate) log_format: Option<LogFormat>,
+
+    /// How to render defmt timestamps: `raw`, `seconds` (ticks divided by `--timestamp-freq`) or
+    /// `iso8601` (prefixed with the host time at which the line was received).
+    #[structopt(long, default_value = "raw")]
+    pub(crate) log_timestamp_format: TimestampFormat,
+
+    /// Frequency in Hz of the clock that defmt timestamps count ticks of.
+    #[structopt(long, required_if("log-timestamp-format", "seconds"))]
+    pub(crate) timestamp_freq: Option<NonZeroU32>,
+
+    /// Halt the device and exit as soon as a log line matches this regular expression.
+    #[structopt(long)]
+    pub(crate) terminate_on: Option<Regex>,
+
+    /// Exit code to use when a log line matched `--terminate-on`.
+    #[structopt(long, default_value = "0")]
+    pub(crate) terminate_code: i32,
+
+    /// Resume the program when it halts on a `BKPT` instruction instead of stopping. Semihosting
+    /// requests are still served; the program has to end with e.g. a semihosting exit or a fault.
+    #[structopt(long)]
+    pub(crate) ignore_breakpoints: bool,
+
+    /// Invert the exit status for tests that are supposed to panic: exit with 0 if the program
+    /// panicked and with 1 if it did not.
+    #[structopt(long)]
+    pub(crate) expect_panic: bool,
+
+    /// Print the ELF flags, ARM build attributes and DWARF versions of the program before running it.
+    #[structopt(long)]
+    pub(crate) show_target_info: bool,
+
+    /// Print the memory behind a symbol (`name` or `name:len`) once the program halted. Rust statics
+    /// are named by their path, e.g. `app::COUNTER`. May be repeated.
+    #[structopt(long, number_of_values = 1)]
+    pub(crate) read_symbol: Vec<SymbolRead>,
+
+    /// Serve `KEY=VALUE` to the program's semihosting `SYS_GET_CMDLINE` requests. May be repeated.
+    #[structopt(long, number_of_values = 1)]
+    pub(crate) env: Vec<EnvVar>,
+
+    /// Print a one-line summary of the run (exit reason, frames, log lines, duration) at the end.
+    #[structopt(long)]
+    pub(crate) summary: bool,
+
+    /// Write flash, log, backtrace and exit events to this file as newline-delimited JSON, e.g.
+    /// `/dev/fd/3`. Human readable output is unaffected.
+    #[structopt(long, parse(from_os_str))]
+    pub(crate) events_file: Option<PathBuf>,
+
     /// Arguments passed after the ELF file path are discarded
     #[structopt(name = "REST")]
     _rest: Vec<String>,
 }
 
+/// Reset performed before the program is started
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum PreRunReset {
+    /// Pull the nRST pin
+    Hard,
+    /// Request a system reset through the core (`SYSRESETREQ`)
+    Soft,
+    /// Only halt the core
+    None,
+}
+
+/// RTT up channels whose output is printed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum RttUpChannels {
+    /// The channel with the given number
+    One(usize),
+    All,
+}
+
+impl FromStr for RttUpChannels {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Self::All),
+            _ => s.parse().map(Self::One).map_err(|_| {
+                anyhow!(
+                    "invalid RTT up channel `{}`; expected a channel number or `all`",
+                    s
+                )
+            }),
+        }
+    }
+}
+
+/// Whether a full RTT up channel makes the program wait for the host to read from it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum RttMode {
+    Blocking,
+    NonBlocking,
+}
+
+impl FromStr for RttMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blocking" => Ok(Self::Blocking),
+            "non-blocking" => Ok(Self::NonBlocking),
+            _ => bail!(
+                "invalid RTT mode `{}`; expected `blocking` or `non-blocking`",
+                s
+            ),
+        }
+    }
+}
+
+impl FromStr for PreRunReset {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hard" => Ok(Self::Hard),
+            "soft" => Ok(Self::Soft),
+            "none" => Ok(Self::None),
+            _ => Err(anyhow!(
+                "invalid reset type `{}`; expected `hard`, `soft` or `none`",
+                s
+            )),
+        }
+    }
+}
+
 pub(crate) fn handle_arguments() -> anyhow::Result<i32> {
-    let opts: Opts = Opts::from_args();
+    let mut opts: Opts = Opts::from_args();
+    opts.path_rules = PathRules::load(&env::current_dir()?)?;
     let verbose = opts.verbose;
+    let quiet = opts.quiet;
+
+    // Don't write ANSI escape codes into files and pipes, or when the user opted out of color.
+    if env::var_os("NO_COLOR").is_some() || !atty::is(atty::Stream::Stdout) {
+        colored::control::set_override(false);
+    }
 
     defmt_decoder::log::init_logger(verbose >= 1, move |metadata| {
         if defmt_decoder::log::is_defmt_frame(metadata) {
             true // We want to display *all* defmt frames.
         } else {
             // Log depending on how often the `--verbose` (`-v`) cli-param is supplied:
+            //   * `--quiet`: log errors from probe-run
             //   * 0: log everything from probe-run, with level "info" or higher
             //   * 1: log everything from probe-run
             //   * 2 or more: log everything
-            if verbose >= 2 {
+            if quiet {
+                metadata.target().starts_with("probe_run") && metadata.level() <= Level::Error
+            } else if verbose >= 2 {
                 true
             } else if verbose >= 1 {
                 metadata.target().starts_with("probe_run")
@@ -94,6 +465,14 @@ pub(crate) fn handle_arguments() -> anyhow::Result<i32> {
         }
     });
 
+    if opts.disasm_on_fault && !cfg!(feature = "disasm") {
+        bail!("`--disasm-on-fault` requires `probe-run` to be built with the `disasm` feature");
+    }
+
+    if let Some(chip_description) = opts.chip_description.as_deref() {
+        load_chip_description(chip_description, opts.chip.as_deref())?;
+    }
+
     if opts.version {
         print_version();
         Ok(EXIT_SUCCESS)
@@ -103,6 +482,26 @@ pub(crate) fn handle_arguments() -> anyhow::Result<i32> {
     } else if opts.list_chips {
         print_chips();
         Ok(EXIT_SUCCESS)
+    } else if opts.query.is_some() {
+        query::print_json(opts.chip.as_deref())?;
+        Ok(EXIT_SUCCESS)
+    } else if let (Some(elf), true) = (opts.elf.as_deref(), opts.dry_run) {
+        crate::dry_run(elf, &opts)
+    } else if let (Some(elf), false) = (opts.elf.as_deref(), opts.symbolize_addr.is_empty()) {
+        crate::symbolize_addresses(elf, &opts.symbolize_addr, &opts)
+    } else if let (Some(elf), Some(range)) = (opts.elf.as_deref(), opts.functions_in_range.clone())
+    {
+        crate::list_functions_in_range(elf, range)
+    } else if let (Some(elf), Some(pc)) = (opts.elf.as_deref(), opts.dump_line_program) {
+        crate::dump_line_program(elf, pc)
+    } else if let (Some(elf), true) = (opts.elf.as_deref(), opts.crate_sizes) {
+        crate::print_crate_sizes(elf)
+    } else if let (Some(elf), Some(rtt_file)) =
+        (opts.elf.as_deref(), opts.decode_rtt_file.as_deref())
+    {
+        crate::decode_rtt_file(elf, rtt_file, &opts)
+    } else if let (Some(elf), Some(core_dump)) = (opts.elf.as_deref(), opts.core_dump.as_deref()) {
+        crate::print_core_dump_backtrace(elf, core_dump, &opts)
     } else if let (Some(elf), Some(chip)) = (opts.elf.as_deref(), opts.chip.as_deref()) {
         crate::run_target_program(elf, chip, &opts)
     } else {
@@ -110,6 +509,27 @@ pub(crate) fn handle_arguments() -> anyhow::Result<i32> {
     }
 }
 
+fn load_chip_description(path: &Path, chip: Option<&str>) -> anyhow::Result<()> {
+    probe_rs::config::add_target_from_yaml(path).with_context(|| {
+        format!(
+            "failed to load the chip description at `{}`",
+            path.display()
+        )
+    })?;
+
+    if let Some(chip) = chip {
+        if probe_rs::config::get_target_by_name(chip).is_err() {
+            bail!(
+                "chip `{}` is neither described in `{}` nor built into probe-rs",
+                chip,
+                path.display()
+            );
+        }
+    }
+
+    Ok(())
+}
+
 fn print_chips() {
     let registry = probe_rs::config::families().expect("Could not retrieve chip family registry");
     for chip_family in registry {
@@ -143,6 +563,31 @@ fn print_version() {
     );
 }
 
+/// Parses a hexadecimal (`0x`-prefixed) or decimal address
+fn parse_address(address: &str) -> Result<u32, ParseIntError> {
+    if let Some(hex) = address
+        .strip_prefix("0x")
+        .or_else(|| address.strip_prefix("0X"))
+    {
+        u32::from_str_radix(&hex.replace('_', ""), 16)
+    } else {
+        address.parse()
+    }
+}
+
+/// Parses a `START..END` address range, with addresses as accepted by `parse_address`
+fn parse_address_range(range: &str) -> anyhow::Result<Range<u32>> {
+    let (start, end) = range
+        .split_once("..")
+        .ok_or_else(|| anyhow!("invalid address range `{}`; expected `START..END`", range))?;
+    let range = parse_address(start)?..parse_address(end)?;
+    if range.start >= range.end {
+        bail!("address range `{:#x?}` is empty", range);
+    }
+
+    Ok(range)
+}
+
 /// Extract git hash from a `git describe` statement
 fn extract_git_hash(git_describe: &str) -> &str {
     git_describe.split('-').nth(2).unwrap()
@@ -162,4 +607,46 @@ mod tests {
         let hash = extract_git_hash(description);
         assert_eq!(hash, expected)
     }
+
+    #[rstest]
+    #[case::number("1", RttUpChannels::One(1))]
+    #[case::all("all", RttUpChannels::All)]
+    fn should_parse_rtt_up_channels(#[case] input: &str, #[case] expected: RttUpChannels) {
+        assert_eq!(input.parse::<RttUpChannels>().unwrap(), expected)
+    }
+
+    #[test]
+    fn should_reject_invalid_rtt_up_channels() {
+        assert!("defmt".parse::<RttUpChannels>().is_err())
+    }
+
+    #[rstest]
+    #[case::blocking("blocking", RttMode::Blocking)]
+    #[case::non_blocking("non-blocking", RttMode::NonBlocking)]
+    fn should_parse_rtt_mode(#[case] input: &str, #[case] expected: RttMode) {
+        assert_eq!(input.parse::<RttMode>().unwrap(), expected)
+    }
+
+    #[rstest]
+    #[case::hex("0x0800_0000..0x0800_1000", 0x0800_0000..0x0800_1000)]
+    #[case::decimal("4096..8192", 4096..8192)]
+    fn should_parse_address_range(#[case] input: &str, #[case] expected: Range<u32>) {
+        assert_eq!(parse_address_range(input).unwrap(), expected)
+    }
+
+    #[rstest]
+    #[case::no_separator("0x1000")]
+    #[case::empty("0x2000..0x1000")]
+    fn should_reject_invalid_address_range(#[case] input: &str) {
+        assert!(parse_address_range(input).is_err())
+    }
+
+    #[rstest]
+    #[case::hex("0x08001234", 0x0800_1234)]
+    #[case::hex_uppercase_prefix("0X1F", 0x1F)]
+    #[case::hex_underscores("0x0800_1234", 0x0800_1234)]
+    #[case::decimal("4096", 4096)]
+    fn should_parse_address(#[case] input: &str, #[case] expected: u32) {
+        assert_eq!(parse_address(input).unwrap(), expected)
+    }
 }
@@ -0,0 +1,140 @@
+//! Register and stack snapshots saved by the program, for backtraces without a target attached
+//!
+//! A core dump file is laid out as follows; all words are little endian:
+//!
+//! | offset | size   | content                                                 |
+//! |--------|--------|---------------------------------------------------------|
+//! | 0      | 4      | PC                                                      |
+//! | 4      | 4      | LR                                                      |
+//! | 8      | 4      | SP                                                      |
+//! | 12     | rest   | stack memory, starting at SP and growing up towards its start |
+//!
+//! A fault handler can produce it by logging PC, LR and SP followed by the memory from SP up to
+//! the start of the stack (e.g. `_stack_start`).
+
+use std::{convert::TryInto, fs, path::Path};
+
+use anyhow::{anyhow, bail, Context as _};
+use probe_rs::CoreRegisterAddress;
+
+use crate::registers::{self, Target};
+
+const HEADER_SIZE: usize = 12;
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct CoreDump {
+    pc: u32,
+    lr: u32,
+    sp: u32,
+    /// Memory at `sp..sp + stack.len()`
+    stack: Vec<u8>,
+}
+
+impl CoreDump {
+    pub(crate) fn read(path: &Path) -> anyhow::Result<Self> {
+        let bytes = fs::read(path)
+            .with_context(|| format!("failed to read core dump `{}`", path.display()))?;
+        Self::parse(&bytes)
+    }
+
+    fn parse(bytes: &[u8]) -> anyhow::Result<Self> {
+        if bytes.len() < HEADER_SIZE {
+            bail!(
+                "core dump is {} bytes long but must at least contain PC, LR and SP ({} bytes)",
+                bytes.len(),
+                HEADER_SIZE
+            );
+        }
+
+        let word = |index: usize| u32::from_le_bytes(bytes[index * 4..][..4].try_into().unwrap());
+        Ok(Self {
+            pc: word(0),
+            lr: word(1),
+            sp: word(2),
+            stack: bytes[HEADER_SIZE..].to_vec(),
+        })
+    }
+}
+
+impl Target for CoreDump {
+    fn read_core_reg(&mut self, reg: CoreRegisterAddress) -> anyhow::Result<u32> {
+        match reg {
+            registers::PC => Ok(self.pc),
+            registers::LR => Ok(self.lr),
+            registers::SP => Ok(self.sp),
+            _ => Err(anyhow!("register r{} is not part of the core dump", reg.0)),
+        }
+    }
+
+    fn read_word_32(&mut self, address: u32) -> anyhow::Result<u32> {
+        let mut word = [0];
+        self.read_32(address, &mut word)?;
+        Ok(word[0])
+    }
+
+    fn read_32(&mut self, address: u32, data: &mut [u32]) -> anyhow::Result<()> {
+        let out_of_bounds = || {
+            anyhow!(
+                "address {:#010x} is outside the stack memory in the core dump ({:#010x}..{:#010x})",
+                address,
+                self.sp,
+                self.sp as usize + self.stack.len()
+            )
+        };
+
+        let start = address.checked_sub(self.sp).ok_or_else(out_of_bounds)? as usize;
+        let bytes = self
+            .stack
+            .get(start..start + data.len() * 4)
+            .ok_or_else(out_of_bounds)?;
+
+        for (word, bytes) in data.iter_mut().zip(bytes.chunks_exact(4)) {
+            *word = u32::from_le_bytes(bytes.try_into().unwrap());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dump() -> CoreDump {
+        let mut bytes = vec![];
+        for word in &[
+            0x0000_0400u32,
+            0x0000_0201,
+            0x2000_fff0,
+            0xdead_beef,
+            0x0000_0301,
+        ] {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        CoreDump::parse(&bytes).unwrap()
+    }
+
+    #[test]
+    fn registers_are_read_from_the_header() {
+        let mut dump = dump();
+
+        assert_eq!(0x0000_0400, dump.read_core_reg(registers::PC).unwrap());
+        assert_eq!(0x0000_0201, dump.read_core_reg(registers::LR).unwrap());
+        assert_eq!(0x2000_fff0, dump.read_core_reg(registers::SP).unwrap());
+        assert!(dump.read_core_reg(CoreRegisterAddress(7)).is_err());
+    }
+
+    #[test]
+    fn stack_starts_at_sp() {
+        let mut dump = dump();
+
+        assert_eq!(0xdead_beef, dump.read_word_32(0x2000_fff0).unwrap());
+        assert_eq!(0x0000_0301, dump.read_word_32(0x2000_fff4).unwrap());
+        assert!(dump.read_word_32(0x2000_ffec).is_err());
+        assert!(dump.read_word_32(0x2000_fff8).is_err());
+    }
+
+    #[test]
+    fn truncated_header_is_rejected() {
+        assert!(CoreDump::parse(&[0; HEADER_SIZE - 1]).is_err());
+    }
+}
@@ -11,11 +11,19 @@ pub(crate) const ADDRESS_SIZE: u8 = mem::size_of::<u32>() as u8;
 pub(crate) const EXC_RETURN_MARKER: u32 = 0xFF00_0000;
 
 pub(crate) const EXC_RETURN_FTYPE_MASK: u32 = 1 << 4;
+/// Bits of xPSR that hold IPSR, the number of the exception being handled (`0` in thread mode)
+const IPSR_MASK: u32 = 0x1FF;
 
 pub(crate) const ENDIANNESS: LittleEndian = LittleEndian;
 pub(crate) type Endianness = LittleEndian;
 
 const THUMB_BIT: u32 = 1;
+const BKPT: u16 = 0xBE00;
+const BKPT_MASK: u16 = 0xFF00;
+/// `BKPT 0xAB`
+pub(crate) const BKPT_SEMIHOSTING: u16 = 0xBEAB;
+/// Size of a `BKPT` instruction in bytes
+pub(crate) const BKPT_SIZE: u32 = 2;
 // According to the ARM Cortex-M Reference Manual RAM memory must be located in this address range
 // (vendors still place e.g. Core-Coupled RAM outside this address range)
 pub(crate) const VALID_RAM_ADDRESS: Range<u32> = 0x2000_0000..0x4000_0000;
@@ -38,6 +46,16 @@ pub(crate) fn set_thumb_bit(addr: u32) -> u32 {
     addr | THUMB_BIT
 }
 
+/// Checks if the Thumb `instruction` is a `BKPT`, excluding the `BKPT 0xAB` semihosting trap
+pub(crate) fn is_breakpoint(instruction: u16) -> bool {
+    instruction & BKPT_MASK == BKPT && instruction != BKPT_SEMIHOSTING
+}
+
+/// Checks if the core is handling an exception, according to the IPSR bits of `xpsr`
+pub(crate) fn is_handler_mode(xpsr: u32) -> bool {
+    xpsr & IPSR_MASK != 0
+}
+
 /// Checks if two subroutine addresses are equivalent by first clearing their `THUMB_BIT`
 pub(crate) fn subroutine_eq(addr1: u32, addr2: u32) -> bool {
     addr1 & !THUMB_BIT == addr2 & !THUMB_BIT
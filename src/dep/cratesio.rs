@@ -1,33 +1,24 @@
-use std::path::{self, Component, Path as StdPath, PathBuf};
+use std::path::{self, Components, Path as StdPath, PathBuf};
 
 use colored::Colorize as _;
 
+use super::rules::{Fields, PathRules};
+
+/// Path into a crates.io dependency, either in the cargo registry or in a `cargo vendor` directory
 #[derive(Debug, PartialEq)]
 pub(crate) struct Path<'p> {
+    /// Directory that contains the crate directories
     registry_prefix: PathBuf,
     crate_name_version: &'p str,
     path: &'p StdPath,
 }
 
 impl<'p> Path<'p> {
-    pub(crate) fn from_std_path(path: &'p StdPath) -> Option<Self> {
-        if !path.is_absolute() {
-            return None;
-        }
-
-        let mut components = path.components();
-
-        let mut registry_prefix = PathBuf::new();
-        for component in &mut components {
-            registry_prefix.push(component.as_os_str());
-
-            if let Component::Normal(component) = component {
-                if component == "registry" {
-                    break;
-                }
-            }
-        }
-
+    /// Parses the `components` that follow `registry_prefix`, which ends in `registry`
+    pub(super) fn after_registry(
+        mut registry_prefix: PathBuf,
+        mut components: Components<'p>,
+    ) -> Option<Self> {
         let src = super::get_component_normal(components.next()?)?;
         if src != "src" {
             return None;
@@ -49,6 +40,72 @@ impl<'p> Path<'p> {
         })
     }
 
+    /// Parses the `components` that follow `registry_prefix`, which ends in `vendor`: the layout of
+    /// `cargo vendor --versioned-dirs`
+    ///
+    /// The vendor directory is part of the workspace, so the path may be relative.
+    pub(super) fn after_vendor(
+        registry_prefix: PathBuf,
+        mut components: Components<'p>,
+    ) -> Option<Self> {
+        let crate_name_version = super::get_component_normal(components.next()?)?.to_str()?;
+
+        let path = Path {
+            registry_prefix,
+            crate_name_version,
+            path: components.as_path(),
+        };
+        // a `vendor` directory that holds something other than crates
+        if path.name_and_version().1.is_empty() {
+            return None;
+        }
+
+        Some(path)
+    }
+
+    /// Returns `true` if the path points into the `compiler_builtins` crate
+    pub(crate) fn is_compiler_builtins(&self) -> bool {
+        self.crate_name_version.starts_with("compiler_builtins-")
+    }
+
+    pub(crate) fn crate_name(&self) -> &'p str {
+        self.name_and_version().0
+    }
+
+    /// Formats the path with the rule for its crate, if there is one
+    pub(crate) fn format_with(&self, rules: &PathRules) -> Option<String> {
+        let (name, version) = self.name_and_version();
+        let full = self
+            .registry_prefix
+            .join(self.crate_name_version)
+            .join(self.path);
+
+        rules.format(&Fields {
+            name,
+            version,
+            path: &self.path.display().to_string(),
+            full: &full.display().to_string(),
+        })
+    }
+
+    /// Splits e.g. `cortex-m-rt-0.6.13` into `cortex-m-rt` and `0.6.13`
+    fn name_and_version(&self) -> (&'p str, &'p str) {
+        // the version is the first `-`-separated part that starts with a digit
+        self.crate_name_version
+            .match_indices('-')
+            .map(|(index, _)| index)
+            .find(|&index| {
+                self.crate_name_version[index + 1..].starts_with(|c: char| c.is_ascii_digit())
+            })
+            .map(|index| {
+                (
+                    &self.crate_name_version[..index],
+                    &self.crate_name_version[index + 1..],
+                )
+            })
+            .unwrap_or((self.crate_name_version, ""))
+    }
+
     pub(crate) fn format_short(&self) -> String {
         format!(
             "[{}]{}{}",
@@ -71,6 +128,8 @@ impl<'p> Path<'p> {
 
 #[cfg(test)]
 mod tests {
+    use crate::dep;
+
     use super::*;
 
     #[test]
@@ -86,7 +145,10 @@ mod tests {
             .join("cortex-m-rt-0.6.13")
             .join("src")
             .join("lib.rs");
-        let path = Path::from_std_path(&input).unwrap();
+        let path = match dep::Path::from_std_path(&input) {
+            dep::Path::Cratesio(path) => path,
+            path => panic!("expected a crates.io path, got {:?}", path),
+        };
 
         let expected = Path {
             registry_prefix: PathBuf::from(home)
@@ -107,4 +169,51 @@ mod tests {
 
         assert_eq!(expected.to_string_lossy(), formatted_str);
     }
+
+    #[test]
+    fn vendored_crate_is_parsed() {
+        let input = PathBuf::from("/work")
+            .join("firmware")
+            .join("vendor")
+            .join("cortex-m-rt-0.6.13")
+            .join("src")
+            .join("lib.rs");
+        let path = match dep::Path::from_std_path(&input) {
+            dep::Path::Cratesio(path) => path,
+            path => panic!("expected a crates.io path, got {:?}", path),
+        };
+
+        let expected = Path {
+            registry_prefix: PathBuf::from("/work").join("firmware").join("vendor"),
+            crate_name_version: "cortex-m-rt-0.6.13",
+            path: &PathBuf::from("src").join("lib.rs"),
+        };
+        assert_eq!(expected, path);
+
+        let expected = PathBuf::from("[cortex-m-rt-0.6.13]")
+            .join("src")
+            .join("lib.rs");
+        assert_eq!(expected.to_string_lossy(), path.format_short());
+    }
+
+    #[test]
+    fn vendor_directory_without_versions_is_not_a_crate() {
+        let input = PathBuf::from("vendor").join("blobs").join("lib.rs");
+
+        assert!(matches!(
+            dep::Path::from_std_path(&input),
+            dep::Path::Verbatim(_)
+        ));
+    }
+
+    #[test]
+    fn name_and_version_are_split() {
+        let path = Path {
+            registry_prefix: PathBuf::new(),
+            crate_name_version: "cortex-m-rt-0.7.0-alpha.1",
+            path: StdPath::new("lib.rs"),
+        };
+
+        assert_eq!(("cortex-m-rt", "0.7.0-alpha.1"), path.name_and_version());
+    }
 }
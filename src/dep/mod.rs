@@ -2,14 +2,17 @@
 
 use std::{
     ffi::OsStr,
-    path::{Component, Path as StdPath},
+    path::{Component, Path as StdPath, PathBuf},
 };
 
 mod cratesio;
+mod rules;
 mod rust_repo;
 mod rust_std;
 mod rustc;
 
+pub(crate) use rules::PathRules;
+
 #[derive(Debug, PartialEq)]
 pub(crate) enum Path<'p> {
     Cratesio(cratesio::Path<'p>),
@@ -22,20 +25,80 @@ pub(crate) enum Path<'p> {
 
 impl<'p> Path<'p> {
     pub(crate) fn from_std_path(path: &'p StdPath) -> Self {
-        if let Some(rust_std) = rust_std::Path::from_std_path(path) {
+        // NOTE `has_root` rather than `is_absolute`: on Windows, paths recorded on a Unix build
+        // machine (e.g. `/home/user/.cargo/...`) have no drive prefix and are not "absolute"
+        let has_root = path.has_root();
+
+        // a single walk over the components; each marker component hands the rest of the path to
+        // its matcher, and the matches are ranked at the end
+        let (mut rust_std, mut rustc, mut registry, mut vendor) = (None, None, None, None);
+        let mut components = path.components();
+        let mut prefix = PathBuf::new();
+        while let Some(component) = components.next() {
+            prefix.push(component);
+
+            let marker = match get_component_normal(component).and_then(OsStr::to_str) {
+                Some(marker) => marker,
+                None => continue,
+            };
+            let rest = components.clone();
+            match marker {
+                "toolchains" if has_root && rust_std.is_none() => {
+                    rust_std = rust_std::Path::after_toolchains(prefix.clone(), rest);
+                    // nothing takes precedence over a toolchain path
+                    if rust_std.is_some() {
+                        break;
+                    }
+                }
+                "rustc" if has_root && rustc.is_none() => {
+                    rustc = rustc::Path::after_rustc(prefix.clone(), rest);
+                }
+                "registry" if has_root && registry.is_none() => {
+                    registry = cratesio::Path::after_registry(prefix.clone(), rest);
+                }
+                // `cargo vendor` puts dependencies into the workspace, where paths may be
+                // relative; the innermost `vendor` directory holds the crate
+                "vendor" => {
+                    if let Some(path) = cratesio::Path::after_vendor(prefix.clone(), rest) {
+                        vendor = Some(path);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(rust_std) = rust_std {
             Self::RustStd(rust_std)
-        } else if let Some(rustc) = rustc::Path::from_std_path(path) {
+        } else if let Some(rustc) = rustc {
             Self::Rustc(rustc)
-        } else if let Some(cratesio) = cratesio::Path::from_std_path(path) {
+        } else if let Some(cratesio) = registry.or(vendor) {
             Self::Cratesio(cratesio)
         } else {
             Self::Verbatim(path)
         }
     }
 
-    pub(crate) fn format_short(&self) -> String {
+    /// Returns `true` if the path points into the `compiler_builtins` crate
+    pub(crate) fn is_compiler_builtins(&self) -> bool {
+        matches!(self, Path::Cratesio(cratesio) if cratesio.is_compiler_builtins())
+    }
+
+    /// Returns the name of the crate the path points into, if it is std or a crates.io dependency
+    pub(crate) fn crate_name(&self) -> Option<&'p str> {
         match self {
-            Path::Cratesio(cratesio) => cratesio.format_short(),
+            Path::Cratesio(cratesio) => Some(cratesio.crate_name()),
+            Path::RustStd(rust_std) => rust_std.crate_name(),
+            Path::Rustc(rustc) => rustc.crate_name(),
+            Path::Verbatim(_) => None,
+        }
+    }
+
+    /// Formats the path in its short form, unless a rule in `rules` says otherwise
+    pub(crate) fn format_short(&self, rules: &PathRules) -> String {
+        match self {
+            Path::Cratesio(cratesio) => cratesio
+                .format_with(rules)
+                .unwrap_or_else(|| cratesio.format_short()),
             Path::RustStd(rust_std) => rust_std.format_short(),
             Path::Rustc(rustc) => rustc.format_short(),
             Path::Verbatim(path) => path.display().to_string(),
@@ -105,7 +168,62 @@ mod tests {
             .join("atomic.rs");
         assert!(matches!(Path::from_std_path(&rust_std), Path::RustStd(_)));
 
+        let vendored = PathBuf::from("vendor")
+            .join("cortex-m-rt-0.6.13")
+            .join("src")
+            .join("lib.rs");
+        assert!(matches!(Path::from_std_path(&vendored), Path::Cratesio(_)));
+
         let local = PathBuf::from("src").join("lib.rs");
         assert!(matches!(Path::from_std_path(&local), Path::Verbatim(_)));
     }
+
+    #[test]
+    fn markers_are_ranked_after_a_single_walk() {
+        // a `rustc` directory that isn't followed by a commit hash
+        let cratesio = StdPath::new(
+            "/home/rustc/.cargo/registry/src/github.com-1ecc6299db9ec823/cortex-m-rt-0.6.13/src/lib.rs",
+        );
+        assert!(matches!(Path::from_std_path(cratesio), Path::Cratesio(_)));
+
+        // a toolchain path wins over the other markers, wherever they are
+        let rust_std = StdPath::new(
+            "/vendor/.rustup/toolchains/stable-x86_64-unknown-linux-gnu/lib/rustlib/src/rust/library/core/src/panicking.rs",
+        );
+        assert!(matches!(Path::from_std_path(rust_std), Path::RustStd(_)));
+
+        // the innermost `vendor` directory holds the crate
+        let vendored = PathBuf::from("vendor")
+            .join("my-tools")
+            .join("vendor")
+            .join("cortex-m-rt-0.6.13")
+            .join("src")
+            .join("lib.rs");
+        match Path::from_std_path(&vendored) {
+            Path::Cratesio(cratesio) => assert_eq!("cortex-m-rt", cratesio.crate_name()),
+            path => panic!("expected a crates.io path, got {:?}", path),
+        }
+
+        // only `vendor` directories can be part of a relative path
+        let relative = PathBuf::from("registry")
+            .join("src")
+            .join("github.com-1ecc6299db9ec823")
+            .join("cortex-m-rt-0.6.13")
+            .join("src")
+            .join("lib.rs");
+        assert!(matches!(Path::from_std_path(&relative), Path::Verbatim(_)));
+    }
+
+    #[test]
+    fn unix_path_is_classified_on_every_host() {
+        let cratesio = StdPath::new(
+            "/home/user/.cargo/registry/src/github.com-1ecc6299db9ec823/cortex-m-rt-0.6.13/src/lib.rs",
+        );
+        assert!(matches!(Path::from_std_path(cratesio), Path::Cratesio(_)));
+
+        let rust_std = StdPath::new(
+            "/home/user/.rustup/toolchains/stable-x86_64-unknown-linux-gnu/lib/rustlib/src/rust/library/core/src/panicking.rs",
+        );
+        assert!(matches!(Path::from_std_path(rust_std), Path::RustStd(_)));
+    }
 }
@@ -0,0 +1,216 @@
+//! Per-crate display rules from the `[paths]` section of `.probe-run.toml`
+
+use std::{fs, io, path::Path as StdPath};
+
+use anyhow::{anyhow, bail, Context as _};
+
+/// Name of the config file looked up in the working directory
+const FILE_NAME: &str = ".probe-run.toml";
+const PLACEHOLDERS: [&str; 4] = ["{name}", "{version}", "{path}", "{full}"];
+
+/// Display templates for the paths of crates.io dependencies, keyed by crate name pattern
+///
+/// ``` toml
+/// [paths]
+/// "cortex-m*" = "[{name}]/{path}"
+/// "my-hal" = "{full}"
+/// ```
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct PathRules {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Rule {
+    /// A crate name; may contain `*` wildcards
+    pattern: String,
+    template: String,
+}
+
+/// The parts of a crates.io path a template can refer to
+pub(crate) struct Fields<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) version: &'a str,
+    /// Path within the crate
+    pub(crate) path: &'a str,
+    pub(crate) full: &'a str,
+}
+
+impl PathRules {
+    /// Reads the rules from `.probe-run.toml` in `dir`, if there is one
+    pub(crate) fn load(dir: &StdPath) -> anyhow::Result<Self> {
+        let path = dir.join(FILE_NAME);
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                Self::from_toml(&contents).with_context(|| format!("invalid {}", path.display()))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    fn from_toml(contents: &str) -> anyhow::Result<Self> {
+        let config = contents.parse::<toml::Value>()?;
+        let paths = match config.get("paths") {
+            Some(paths) => paths
+                .as_table()
+                .ok_or_else(|| anyhow!("`paths` must be a table"))?,
+            None => return Ok(Self::default()),
+        };
+
+        let mut rules = vec![];
+        for (pattern, template) in paths {
+            let template = template
+                .as_str()
+                .ok_or_else(|| anyhow!("the template for `{}` must be a string", pattern))?;
+            check_placeholders(template)?;
+
+            rules.push(Rule {
+                pattern: pattern.clone(),
+                template: template.to_string(),
+            });
+        }
+
+        Ok(Self { rules })
+    }
+
+    /// Formats the path with the rule for its crate; `None` if no rule matches
+    ///
+    /// An exact crate name beats a pattern, and a longer pattern beats a shorter one.
+    pub(crate) fn format(&self, fields: &Fields) -> Option<String> {
+        let rule = self
+            .rules
+            .iter()
+            .filter(|rule| matches(&rule.pattern, fields.name))
+            .max_by_key(|rule| (!rule.pattern.contains('*'), rule.pattern.len()))?;
+
+        Some(
+            rule.template
+                .replace("{name}", fields.name)
+                .replace("{version}", fields.version)
+                .replace("{path}", fields.path)
+                .replace("{full}", fields.full),
+        )
+    }
+}
+
+fn check_placeholders(template: &str) -> anyhow::Result<()> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow!("unclosed `{{` in path template `{}`", template))?;
+        let placeholder = &rest[start..start + end + 1];
+        if !PLACEHOLDERS.contains(&placeholder) {
+            bail!(
+                "unknown placeholder `{}` in path template; expected one of {}",
+                placeholder,
+                PLACEHOLDERS.join(", ")
+            );
+        }
+        rest = &rest[start + end + 1..];
+    }
+
+    Ok(())
+}
+
+/// Matches `name` against `pattern`, in which `*` stands for any (possibly empty) string
+fn matches(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => {
+            let tail = match name.strip_prefix(prefix) {
+                Some(tail) => tail,
+                None => return false,
+            };
+            // try every position the wildcard could end at
+            (0..=tail.len())
+                .filter(|&i| tail.is_char_boundary(i))
+                .any(|i| matches(rest, &tail[i..]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELDS: Fields = Fields {
+        name: "cortex-m-rt",
+        version: "0.6.13",
+        path: "src/lib.rs",
+        full: "/home/user/.cargo/registry/src/github.com-1ecc6299db9ec823/cortex-m-rt-0.6.13/src/lib.rs",
+    };
+
+    #[test]
+    fn wildcards_match_any_string() {
+        assert!(matches("cortex-m*", "cortex-m-rt"));
+        assert!(matches("*-rt", "cortex-m-rt"));
+        assert!(matches("c*m*t", "cortex-m-rt"));
+        assert!(matches("*", "defmt"));
+        assert!(!matches("cortex-m", "cortex-m-rt"));
+        assert!(!matches("*-hal", "cortex-m-rt"));
+    }
+
+    #[test]
+    fn template_is_filled_in() {
+        let rules = PathRules::from_toml(
+            r#"
+            [paths]
+            "cortex-m*" = "[{name}]/{path}"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            Some("[cortex-m-rt]/src/lib.rs".to_string()),
+            rules.format(&FIELDS)
+        );
+    }
+
+    #[test]
+    fn exact_name_beats_pattern() {
+        let rules = PathRules::from_toml(
+            r#"
+            [paths]
+            "*" = "{name}/{path}"
+            "cortex-m-rt" = "{full}"
+            "cortex-m*" = "[{name}]/{path}"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(Some(FIELDS.full.to_string()), rules.format(&FIELDS));
+    }
+
+    #[test]
+    fn unmatched_crate_has_no_rule() {
+        let rules = PathRules::from_toml(
+            r#"
+            [paths]
+            "nrf*" = "{name}/{path}"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(None, rules.format(&FIELDS));
+    }
+
+    #[test]
+    fn missing_paths_section_has_no_rules() {
+        assert_eq!(
+            PathRules::default(),
+            PathRules::from_toml("[other]\nkey = 1").unwrap()
+        );
+    }
+
+    #[test]
+    fn unknown_placeholder_is_rejected() {
+        assert!(PathRules::from_toml("[paths]\n\"*\" = \"{crate}/{path}\"").is_err());
+    }
+
+    #[test]
+    fn non_string_template_is_rejected() {
+        assert!(PathRules::from_toml("[paths]\n\"*\" = 1").is_err());
+    }
+}
@@ -18,6 +18,13 @@ impl<'p> Path<'p> {
         }
     }
 
+    pub(crate) fn crate_name(&self) -> Option<&'p str> {
+        match self {
+            Path::One52(path) => Some(path.crate_name),
+            Path::Verbatim(_) => None,
+        }
+    }
+
     pub(crate) fn format(&self) -> String {
         match self {
             Path::One52(path) => path.format(),
@@ -1,4 +1,4 @@
-use std::path::{self, Component, Path as StdPath, PathBuf};
+use std::path::{self, Component, Components, PathBuf};
 
 use colored::Colorize;
 
@@ -17,24 +17,11 @@ pub(crate) struct Path<'p> {
 }
 
 impl<'p> Path<'p> {
-    pub(crate) fn from_std_path(path: &'p StdPath) -> Option<Self> {
-        if !path.is_absolute() {
-            return None;
-        }
-
-        let mut components = path.components();
-
-        let mut rustup_prefix = PathBuf::new();
-        for component in &mut components {
-            rustup_prefix.push(component);
-
-            if let Component::Normal(component) = component {
-                if component == "toolchains" {
-                    break;
-                }
-            }
-        }
-
+    /// Parses the `components` that follow `rustup_prefix`, which ends in `toolchains`
+    pub(super) fn after_toolchains(
+        rustup_prefix: PathBuf,
+        mut components: Components<'p>,
+    ) -> Option<Self> {
         let toolchain =
             Toolchain::from_str(super::get_component_normal(components.next()?)?.to_str()?);
 
@@ -59,6 +46,11 @@ impl<'p> Path<'p> {
         })
     }
 
+    /// Returns the name of the std crate, e.g. `core`, if the path has the known layout
+    pub(crate) fn crate_name(&self) -> Option<&'p str> {
+        self.rust_repo_path.crate_name()
+    }
+
     pub(crate) fn format_short(&self) -> String {
         format!(
             "[{}]{}{}",
@@ -84,6 +76,8 @@ impl<'p> Path<'p> {
 mod tests {
     use pretty_assertions::assert_eq;
 
+    use crate::dep;
+
     use super::*;
 
     #[test]
@@ -105,7 +99,10 @@ mod tests {
             .join("sync")
             .join("atomic.rs");
 
-        let path = Path::from_std_path(&input).unwrap();
+        let path = match dep::Path::from_std_path(&input) {
+            dep::Path::RustStd(path) => path,
+            path => panic!("expected a rust-std path, got {:?}", path),
+        };
 
         let src_path = PathBuf::from("src").join("sync").join("atomic.rs");
 
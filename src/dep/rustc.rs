@@ -1,4 +1,4 @@
-use std::path::{self, Component, Path as StdPath, PathBuf};
+use std::path::{self, Components, PathBuf};
 
 use colored::Colorize;
 
@@ -11,24 +11,11 @@ pub(crate) struct Path<'p> {
 }
 
 impl<'p> Path<'p> {
-    pub(crate) fn from_std_path(path: &'p StdPath) -> Option<Self> {
-        if !path.is_absolute() {
-            return None;
-        }
-
-        let mut components = path.components();
-
-        let mut rustc_prefix = PathBuf::new();
-        for component in &mut components {
-            rustc_prefix.push(component);
-
-            if let Component::Normal(component) = component {
-                if component == "rustc" {
-                    break;
-                }
-            }
-        }
-
+    /// Parses the `components` that follow `rustc_prefix`, which ends in `rustc`
+    pub(super) fn after_rustc(
+        mut rustc_prefix: PathBuf,
+        mut components: Components<'p>,
+    ) -> Option<Self> {
         let hash = super::get_component_normal(components.next()?)?.to_str()?;
         if !hash.chars().all(|c| char::is_ascii_hexdigit(&c)) {
             return None;
@@ -43,6 +30,11 @@ impl<'p> Path<'p> {
         })
     }
 
+    /// Returns the name of the std crate, e.g. `core`, if the path has the known layout
+    pub(crate) fn crate_name(&self) -> Option<&'p str> {
+        self.rust_repo_path.crate_name()
+    }
+
     pub(crate) fn format_short(&self) -> String {
         format!(
             "[rust]{}{}",
@@ -63,6 +55,8 @@ impl<'p> Path<'p> {
 
 #[cfg(test)]
 mod tests {
+    use crate::dep;
+
     use super::*;
 
     #[test]
@@ -82,7 +76,10 @@ mod tests {
             .join("rustc")
             .join("9bc8c42bb2f19e745a63f3445f1ac248fb015e53");
 
-        let path = Path::from_std_path(&input).unwrap();
+        let path = match dep::Path::from_std_path(&input) {
+            dep::Path::Rustc(path) => path,
+            path => panic!("expected a rustc path, got {:?}", path),
+        };
         let expected_path = PathBuf::from("src").join("panicking.rs");
         let expected = Path {
             rustc_prefix,
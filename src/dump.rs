@@ -0,0 +1,158 @@
+//! Dumping the target's memory by symbol name
+
+use std::{convert::TryInto, fmt::Write as _, str::FromStr};
+
+use anyhow::{anyhow, bail};
+use probe_rs::{Core, MemoryInterface as _};
+
+use crate::elf::Elf;
+
+const BYTES_PER_LINE: usize = 16;
+
+/// A `--read-symbol` request: `name` or `name:len`
+#[derive(Debug, PartialEq)]
+pub(crate) struct SymbolRead {
+    name: String,
+    /// Overrides the size recorded in the symbol table
+    len: Option<u32>,
+}
+
+impl FromStr for SymbolRead {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // the `::` in the path of a Rust symbol doesn't separate a length
+        match s.rsplit_once(':') {
+            Some((name, len)) if !name.ends_with(':') && !len.is_empty() => {
+                Ok(Self {
+                    name: name.to_string(),
+                    len: Some(len.parse().map_err(|_| {
+                        anyhow!("invalid length `{}` in `--read-symbol {}`", len, s)
+                    })?),
+                })
+            }
+            _ => Ok(Self {
+                name: s.to_string(),
+                len: None,
+            }),
+        }
+    }
+}
+
+/// Reads the memory behind the requested symbol and prints it as a hex dump
+pub(crate) fn print(core: &mut Core, elf: &Elf, read: &SymbolRead) -> anyhow::Result<()> {
+    let (address, len) = locate(elf, read)?;
+
+    let mut bytes = vec![0; len as usize];
+    core.read_8(address, &mut bytes)?;
+
+    println!(
+        "symbol `{}` @ {:#010x} ({} bytes):",
+        read.name, address, len
+    );
+    print!("{}", hexdump(address, &bytes));
+
+    Ok(())
+}
+
+/// Returns the address and the number of bytes to read
+fn locate(elf: &Elf, read: &SymbolRead) -> anyhow::Result<(u32, u32)> {
+    let (address, size) = elf
+        .symbol_address_and_size(&read.name)
+        .ok_or_else(|| anyhow!("symbol `{}` not found", read.name))?;
+    let len = match read.len {
+        Some(len) => len,
+        None if size == 0 => bail!(
+            "symbol `{}` has no size; pass the number of bytes to read as `--read-symbol {}:<len>`",
+            read.name,
+            read.name
+        ),
+        None => size.try_into()?,
+    };
+
+    Ok((address, len))
+}
+
+fn hexdump(address: u32, bytes: &[u8]) -> String {
+    let mut output = String::new();
+    for (index, line) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        let line_address = address + (index * BYTES_PER_LINE) as u32;
+        let hex = line
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>()
+            .join(" ");
+        writeln!(output, "  {:08x}: {}", line_address, hex).unwrap();
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_name_and_length() {
+        let expected = SymbolRead {
+            name: "BUFFER".to_string(),
+            len: Some(32),
+        };
+        assert_eq!(expected, "BUFFER:32".parse().unwrap());
+
+        let expected = SymbolRead {
+            name: "COUNTER".to_string(),
+            len: None,
+        };
+        assert_eq!(expected, "COUNTER".parse().unwrap());
+
+        assert!("BUFFER:lots".parse::<SymbolRead>().is_err());
+    }
+
+    #[test]
+    fn rust_paths_are_not_split() {
+        let expected = SymbolRead {
+            name: "app::COUNTER".to_string(),
+            len: None,
+        };
+        assert_eq!(expected, "app::COUNTER".parse().unwrap());
+
+        let expected = SymbolRead {
+            name: "app::COUNTER".to_string(),
+            len: Some(4),
+        };
+        assert_eq!(expected, "app::COUNTER:4".parse().unwrap());
+    }
+
+    #[test]
+    fn symbols_are_found_by_demangled_path() {
+        let elf_bytes = include_bytes!("../tests/test_elfs/hello");
+        let elf = Elf::parse(elf_bytes).unwrap();
+
+        let read = "defmt_rtt::handle::BUFFER".parse().unwrap();
+        assert_eq!((0x2003_fbfc, 1024), locate(&elf, &read).unwrap());
+
+        let read = "_SEGGER_RTT".parse().unwrap();
+        assert_eq!((0x2003_fbc8, 48), locate(&elf, &read).unwrap());
+    }
+
+    #[test]
+    fn zero_size_symbol_needs_a_length() {
+        let elf_bytes = include_bytes!("../tests/test_elfs/hello");
+        let elf = Elf::parse(elf_bytes).unwrap();
+
+        assert!(locate(&elf, &".L__unnamed_11".parse().unwrap()).is_err());
+        assert_eq!(
+            (0x0000_13a4, 8),
+            locate(&elf, &".L__unnamed_11:8".parse().unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn hexdump_splits_lines() {
+        let bytes = (0..20).collect::<Vec<u8>>();
+
+        let expected = "  20000000: 00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f\n  \
+                        20000010: 10 11 12 13\n";
+        assert_eq!(expected, hexdump(0x2000_0000, &bytes));
+    }
+}
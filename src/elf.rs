@@ -3,8 +3,8 @@ use std::{collections::HashSet, convert::TryInto, env, ops::Deref};
 use anyhow::{anyhow, bail};
 use defmt_decoder::{Locations, Table};
 use object::{
-    read::File as ObjectFile, Object as _, ObjectSection as _, ObjectSegment as _,
-    ObjectSymbol as _, SymbolSection,
+    read::File as ObjectFile, FileKind, Object as _, ObjectKind, ObjectSection as _,
+    ObjectSegment as _, ObjectSymbol as _, SymbolKind, SymbolSection,
 };
 
 use crate::cortexm;
@@ -21,7 +21,16 @@ pub(crate) struct Elf<'file> {
 
 impl<'file> Elf<'file> {
     pub(crate) fn parse(elf_bytes: &'file [u8]) -> Result<Self, anyhow::Error> {
+        check_file_kind(elf_bytes)?;
+
         let elf = ObjectFile::parse(elf_bytes)?;
+        if elf.kind() != ObjectKind::Executable {
+            bail!(
+                "expected an executable ELF file, got a {:?} one; \
+                make sure to pass the linked firmware binary, not an object file",
+                elf.kind()
+            );
+        }
 
         let live_functions = extract_live_functions(&elf)?;
 
@@ -56,6 +65,38 @@ impl<'file> Elf<'file> {
         self.symbols.rtt_buffer_address
     }
 
+    /// Returns the address and size of the symbol called `name`: its name in the symbol table or,
+    /// for a mangled Rust symbol, its demangled path without the hash, e.g. `app::COUNTER`
+    pub(crate) fn symbol_address_and_size(&self, name: &str) -> Option<(u32, u64)> {
+        self.elf
+            .symbols()
+            .find(|symbol| match symbol.name() {
+                Ok(symbol_name) => {
+                    symbol_name == name
+                        || rustc_demangle::try_demangle(symbol_name)
+                            .map_or(false, |demangled| format!("{:#}", demangled) == name)
+                }
+                Err(_) => false,
+            })
+            .map(|symbol| {
+                let address = symbol.address().try_into().expect("expected 32-bit ELF");
+                (address, symbol.size())
+            })
+    }
+
+    /// Returns the contents of the section called `name`, or nothing if there's no such section
+    pub(crate) fn section_data(&self, name: &str) -> anyhow::Result<&'file [u8]> {
+        Ok(match self.elf.section_by_name(name) {
+            Some(section) => section.data()?,
+            None => &[],
+        })
+    }
+
+    /// Returns the number of function symbols in the symbol table, live or not
+    pub(crate) fn num_functions(&self) -> usize {
+        self.symbols.num_functions
+    }
+
     /// Returns the size of the part of the program allocated in Flash
     pub(crate) fn program_flash_size(&self) -> u64 {
         // `segments` iterates only over *loadable* segments,
@@ -72,6 +113,20 @@ impl<'elf> Deref for Elf<'elf> {
     }
 }
 
+fn check_file_kind(bytes: &[u8]) -> anyhow::Result<()> {
+    match FileKind::parse(bytes)? {
+        FileKind::Elf32 => Ok(()),
+        FileKind::Archive => bail!(
+            "expected an executable ELF file, got a static library; \
+            pass the firmware binary from `target/<triple>/<profile>/` instead"
+        ),
+        kind => bail!(
+            "expected a 32-bit executable ELF file, got a {:?} file",
+            kind
+        ),
+    }
+}
+
 fn extract_live_functions<'file>(elf: &ObjectFile<'file>) -> anyhow::Result<HashSet<&'file str>> {
     let text = elf
         .section_by_name(".text")
@@ -94,6 +149,12 @@ fn extract_live_functions<'file>(elf: &ObjectFile<'file>) -> anyhow::Result<Hash
         })
         .collect::<Result<HashSet<_>, _>>()?;
 
+    if live_functions.is_empty() {
+        log::error!(
+            "no function symbols found -- was the binary stripped? backtraces will be unavailable"
+        );
+    }
+
     Ok(live_functions)
 }
 
@@ -172,6 +233,7 @@ fn extract_debug_frame<'file>(elf: &ObjectFile<'file>) -> anyhow::Result<DebugFr
 }
 
 struct Symbols {
+    num_functions: usize,
     rtt_buffer_address: Option<u32>,
     program_uses_heap: bool,
     main_fn_address: u32,
@@ -181,8 +243,13 @@ fn extract_symbols(elf: &ObjectFile) -> anyhow::Result<Symbols> {
     let mut rtt_buffer_address = None;
     let mut program_uses_heap = false;
     let mut main_fn_address = None;
+    let mut num_functions = 0;
 
     for symbol in elf.symbols() {
+        if symbol.kind() == SymbolKind::Text {
+            num_functions += 1;
+        }
+
         let name = match symbol.name() {
             Ok(name) => name,
             Err(_) => continue,
@@ -204,6 +271,7 @@ fn extract_symbols(elf: &ObjectFile) -> anyhow::Result<Symbols> {
         main_fn_address.ok_or_else(|| anyhow!("`main` symbol not found"))?;
 
     Ok(Symbols {
+        num_functions,
         rtt_buffer_address,
         program_uses_heap,
         main_fn_address: main_function_address,
@@ -0,0 +1,65 @@
+//! Machine readable stream of run events, written as newline-delimited JSON
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write as _},
+    path::Path,
+};
+
+use anyhow::Context as _;
+use serde_json::{json, Value};
+
+/// Destination of `--events-file`; all events are dropped if the flag wasn't passed
+pub(crate) struct Events {
+    writer: Option<BufWriter<File>>,
+}
+
+impl Events {
+    pub(crate) fn create(path: Option<&Path>) -> anyhow::Result<Self> {
+        let writer = path
+            .map(|path| {
+                File::create(path)
+                    .with_context(|| format!("failed to create events file `{}`", path.display()))
+            })
+            .transpose()?
+            .map(BufWriter::new);
+
+        Ok(Self { writer })
+    }
+
+    /// Writes one line `{"event": <event>, ...fields}`
+    pub(crate) fn emit(&mut self, event: &str, fields: Value) -> anyhow::Result<()> {
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => return Ok(()),
+        };
+
+        writeln!(writer, "{}", line(event, fields))?;
+        // consumers may be following the file while the program runs
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+fn line(event: &str, fields: Value) -> Value {
+    let mut line = json!({ "event": event });
+    if let (Value::Object(line), Value::Object(fields)) = (&mut line, fields) {
+        line.extend(fields);
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_name_comes_first() {
+        let line = line("exit", json!({ "outcome": "Ok", "code": 0 }));
+
+        assert_eq!(
+            r#"{"event":"exit","outcome":"Ok","code":0}"#,
+            line.to_string()
+        );
+    }
+}
@@ -0,0 +1,235 @@
+//! Progress reporting while erasing and programming the flash
+
+use std::{
+    cell::RefCell,
+    io::{self, Write as _},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use probe_rs::flashing::{FlashProgress, ProgressEvent};
+
+const BAR_WIDTH: usize = 30;
+/// How often (in percent) a progress line is printed when stderr is not a terminal
+const LINE_STEP: u64 = 25;
+
+#[derive(Clone, Copy)]
+enum Phase {
+    Erasing,
+    Programming,
+}
+
+#[derive(Default)]
+struct State {
+    erase_total: u64,
+    erased: u64,
+    program_total: u64,
+    programmed: u64,
+    /// Last percentage printed as a line (non-TTY output only)
+    reported: Option<u64>,
+}
+
+/// How often the `Watchdog` checks for progress
+const WATCHDOG_POLL: Duration = Duration::from_millis(100);
+
+/// Returns a progress handler that draws a bar on a terminal or prints periodic lines otherwise
+///
+/// Nothing is printed unless `show`; every event feeds the `watchdog`, if any.
+pub(crate) fn new(is_tty: bool, show: bool, watchdog: Option<Watchdog>) -> FlashProgress {
+    let state = RefCell::new(State::default());
+
+    FlashProgress::new(move |event| {
+        if let Some(watchdog) = &watchdog {
+            watchdog.feed();
+        }
+        if !show {
+            return;
+        }
+
+        let mut state = state.borrow_mut();
+        match event {
+            // sent once per flash region, while the done counters run across all of them
+            ProgressEvent::Initialized { flash_layout } => {
+                state.erase_total += flash_layout
+                    .sectors()
+                    .iter()
+                    .map(|sector| sector.size() as u64)
+                    .sum::<u64>();
+                state.program_total += flash_layout
+                    .pages()
+                    .iter()
+                    .map(|page| page.size() as u64)
+                    .sum::<u64>();
+            }
+            ProgressEvent::StartedErasing | ProgressEvent::StartedProgramming => {
+                state.reported = None;
+            }
+            ProgressEvent::SectorErased { size, .. } => {
+                state.erased += size as u64;
+                let (done, total) = (state.erased, state.erase_total);
+                state.report(Phase::Erasing, done, total, is_tty);
+            }
+            ProgressEvent::PageProgrammed { size, .. } => {
+                state.programmed += size as u64;
+                let (done, total) = (state.programmed, state.program_total);
+                state.report(Phase::Programming, done, total, is_tty);
+            }
+            // move past the progress bar
+            ProgressEvent::FinishedErasing
+            | ProgressEvent::FailedErasing
+            | ProgressEvent::FinishedProgramming
+            | ProgressEvent::FailedProgramming
+                if is_tty =>
+            {
+                eprintln!();
+            }
+            _ => {}
+        }
+    })
+}
+
+/// `--flash-timeout`: exits the process with `crate::EXIT_FLASH_TIMEOUT` if flashing makes no
+/// progress for too long, after running the `--after-run` hook
+///
+/// Flashing runs on the main thread and a stuck probe may never return from it, so the process is
+/// exited from a separate thread. The probe session is not torn down.
+#[derive(Clone)]
+pub(crate) struct Watchdog {
+    last_progress: Arc<Mutex<Instant>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Watchdog {
+    pub(crate) fn start(timeout: Duration, after_run: Option<String>) -> Self {
+        let watchdog = Self {
+            last_progress: Arc::new(Mutex::new(Instant::now())),
+            stopped: Arc::new(AtomicBool::new(false)),
+        };
+
+        let this = watchdog.clone();
+        thread::spawn(move || {
+            while !this.stopped.load(Ordering::Relaxed) {
+                if this.last_progress.lock().unwrap().elapsed() > timeout {
+                    let error = format!(
+                        "flashing exceeded timeout of {}s, possible probe/USB issue",
+                        timeout.as_secs()
+                    );
+                    log::error!("{}", error);
+                    crate::exit_on_timeout(
+                        after_run.as_deref(),
+                        "flash-timeout",
+                        crate::EXIT_FLASH_TIMEOUT,
+                        &error,
+                    );
+                }
+                thread::sleep(WATCHDOG_POLL);
+            }
+        });
+
+        watchdog
+    }
+
+    /// Restarts the timeout
+    fn feed(&self) {
+        *self.last_progress.lock().unwrap() = Instant::now();
+    }
+
+    /// Stops watching; flashing has ended, successfully or not
+    pub(crate) fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+impl State {
+    fn report(&mut self, phase: Phase, done: u64, total: u64, is_tty: bool) {
+        let percent = percent(done, total);
+        if is_tty {
+            eprint!("\r{}", bar(phase, percent));
+            let _ = io::stderr().flush();
+        } else {
+            let step = percent - percent % LINE_STEP;
+            if self.reported != Some(step) {
+                self.reported = Some(step);
+                eprintln!("{}", line(phase, done, total));
+            }
+        }
+    }
+}
+
+fn percent(done: u64, total: u64) -> u64 {
+    if total == 0 {
+        100
+    } else {
+        (done * 100 / total).min(100)
+    }
+}
+
+fn bar(phase: Phase, percent: u64) -> String {
+    let filled = percent as usize * BAR_WIDTH / 100;
+    format!(
+        "{:<11} [{}{}] {:>3}%",
+        phase.as_str(),
+        "#".repeat(filled),
+        " ".repeat(BAR_WIDTH - filled),
+        percent
+    )
+}
+
+fn line(phase: Phase, done: u64, total: u64) -> String {
+    format!(
+        "{} {:.02}/{:.02} KiB",
+        phase.as_past_str(),
+        done as f64 / 1024.0,
+        total as f64 / 1024.0
+    )
+}
+
+impl Phase {
+    fn as_str(self) -> &'static str {
+        match self {
+            Phase::Erasing => "erasing",
+            Phase::Programming => "programming",
+        }
+    }
+
+    fn as_past_str(self) -> &'static str {
+        match self {
+            Phase::Erasing => "erased",
+            Phase::Programming => "flashed",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bar_is_filled_proportionally() {
+        assert_eq!(
+            "erasing     [###############               ]  50%",
+            bar(Phase::Erasing, 50)
+        );
+        assert_eq!(
+            "programming [##############################] 100%",
+            bar(Phase::Programming, 100)
+        );
+    }
+
+    #[test]
+    fn line_reports_kibibytes() {
+        assert_eq!(
+            "flashed 12.00/48.50 KiB",
+            line(Phase::Programming, 12 * 1024, 48 * 1024 + 512)
+        );
+    }
+
+    #[test]
+    fn empty_phase_is_complete() {
+        assert_eq!(100, percent(0, 0));
+    }
+}
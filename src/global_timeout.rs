@@ -0,0 +1,88 @@
+//! `--global-timeout`: a bound on the whole invocation, so that a CI job can't hang on a wedged
+//! probe
+
+use std::{
+    sync::atomic::{AtomicU8, Ordering},
+    thread,
+    time::Duration,
+};
+
+/// The `Phase` the invocation is in
+static PHASE: AtomicU8 = AtomicU8::new(Phase::Attaching as u8);
+
+/// Coarse progress of the invocation, reported when the timeout expires
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub(crate) enum Phase {
+    Attaching,
+    Flashing,
+    Running,
+    TearingDown,
+}
+
+impl Phase {
+    fn from_u8(phase: u8) -> Self {
+        match phase {
+            0 => Self::Attaching,
+            1 => Self::Flashing,
+            2 => Self::Running,
+            _ => Self::TearingDown,
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Self::Attaching => "attaching to the target",
+            Self::Flashing => "flashing the program",
+            Self::Running => "running the program",
+            Self::TearingDown => "tearing down the session",
+        }
+    }
+}
+
+/// Records that the invocation moved on to `phase`
+pub(crate) fn enter(phase: Phase) {
+    PHASE.store(phase as u8, Ordering::Relaxed);
+}
+
+/// Exits the process with `crate::EXIT_GLOBAL_TIMEOUT` once `timeout` has passed, after running
+/// the `after_run` hook
+///
+/// The process is exited from a separate thread because the main thread may be stuck in a probe
+/// operation that never returns. The probe session is not torn down.
+pub(crate) fn start(timeout: Duration, after_run: Option<String>) {
+    thread::spawn(move || {
+        thread::sleep(timeout);
+
+        let phase = Phase::from_u8(PHASE.load(Ordering::Relaxed));
+        let error = format!(
+            "global timeout of {}s expired while {}",
+            timeout.as_secs(),
+            phase.describe()
+        );
+        log::error!("{}", error);
+        crate::exit_on_timeout(
+            after_run.as_deref(),
+            "global-timeout",
+            crate::EXIT_GLOBAL_TIMEOUT,
+            &error,
+        );
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phase_round_trips_through_u8() {
+        for &phase in &[
+            Phase::Attaching,
+            Phase::Flashing,
+            Phase::Running,
+            Phase::TearingDown,
+        ] {
+            assert_eq!(phase, Phase::from_u8(phase as u8));
+        }
+    }
+}
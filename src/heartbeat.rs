@@ -0,0 +1,95 @@
+//! Status line shown on a terminal while the program runs without logging
+
+use std::{
+    io::{self, Write as _},
+    time::{Duration, Instant},
+};
+
+/// How long the program has to be silent before the status line appears
+const IDLE: Duration = Duration::from_secs(2);
+/// How often the status line is redrawn
+const REDRAW: Duration = Duration::from_millis(500);
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+pub(crate) struct Heartbeat {
+    enabled: bool,
+    start: Instant,
+    last_log: Instant,
+    last_draw: Option<Instant>,
+    num_draws: usize,
+}
+
+impl Heartbeat {
+    /// Returns a heartbeat that draws nothing unless `enabled`, e.g. when stderr is not a terminal
+    pub(crate) fn new(enabled: bool) -> Self {
+        let now = Instant::now();
+        Self {
+            enabled,
+            start: now,
+            last_log: now,
+            last_draw: None,
+            num_draws: 0,
+        }
+    }
+
+    /// Draws or updates the status line in place if no log arrived for a while
+    pub(crate) fn tick(&mut self, num_logs: usize) {
+        if !self.enabled || self.last_log.elapsed() < IDLE {
+            return;
+        }
+
+        if let Some(last_draw) = self.last_draw {
+            if last_draw.elapsed() < REDRAW {
+                return;
+            }
+        }
+
+        let spinner = SPINNER[self.num_draws % SPINNER.len()];
+        eprint!("\r{}", status(spinner, num_logs, self.start.elapsed()));
+        let _ = io::stderr().flush();
+        self.last_draw = Some(Instant::now());
+        self.num_draws += 1;
+    }
+
+    /// Removes the status line before a log is printed
+    pub(crate) fn clear(&mut self) {
+        self.last_log = Instant::now();
+
+        if self.last_draw.take().is_some() {
+            // erase the whole line and return to its start
+            eprint!("\r\x1b[2K");
+            let _ = io::stderr().flush();
+        }
+    }
+}
+
+fn status(spinner: char, num_logs: usize, elapsed: Duration) -> String {
+    format!(
+        "{} (still running, {} logs, {}s elapsed)",
+        spinner,
+        num_logs,
+        elapsed.as_secs()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_shows_logs_and_elapsed_seconds() {
+        assert_eq!(
+            "/ (still running, 312 logs, 42s elapsed)",
+            status('/', 312, Duration::from_millis(42_900))
+        );
+    }
+
+    #[test]
+    fn disabled_heartbeat_draws_nothing() {
+        let mut heartbeat = Heartbeat::new(false);
+        heartbeat.last_log -= IDLE;
+        heartbeat.tick(0);
+
+        assert!(heartbeat.last_draw.is_none());
+    }
+}
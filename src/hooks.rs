@@ -0,0 +1,90 @@
+//! Host commands run before and after the program, e.g. to power a board through a relay
+
+use std::process::{Command, ExitStatus};
+
+use anyhow::{bail, Context as _};
+
+/// Name of the environment variable that tells `--after-run` why the program stopped
+pub(crate) const EXIT_REASON_VAR: &str = "PROBE_RUN_EXIT_REASON";
+/// Name of the environment variable that tells `--after-run` the exit code of `probe-run`
+pub(crate) const EXIT_CODE_VAR: &str = "PROBE_RUN_EXIT_CODE";
+/// Name of the environment variable that tells `--after-run` the error `probe-run` failed with
+pub(crate) const ERROR_VAR: &str = "PROBE_RUN_ERROR";
+
+/// Runs the `--before-run` command; fails unless it succeeds
+pub(crate) fn before_run(command: &str) -> anyhow::Result<()> {
+    let status = run(command, &[])?;
+    if !status.success() {
+        bail!("`--before-run` command `{}` failed ({})", command, status);
+    }
+
+    Ok(())
+}
+
+/// Runs the `--after-run` command; a failure is only reported
+///
+/// `error` is set if `probe-run` failed, in which case `exit_reason` is `error`
+pub(crate) fn after_run(
+    command: &str,
+    exit_reason: &str,
+    exit_code: i32,
+    error: Option<&str>,
+) -> anyhow::Result<()> {
+    let mut envs = vec![
+        (EXIT_REASON_VAR, exit_reason.to_string()),
+        (EXIT_CODE_VAR, exit_code.to_string()),
+    ];
+    if let Some(error) = error {
+        envs.push((ERROR_VAR, error.to_string()));
+    }
+
+    let status = run(command, &envs)?;
+    if !status.success() {
+        log::warn!("`--after-run` command `{}` failed ({})", command, status);
+    }
+
+    Ok(())
+}
+
+/// Runs `command` in the platform's shell, inheriting stdio
+fn run(command: &str, envs: &[(&str, String)]) -> anyhow::Result<ExitStatus> {
+    log::debug!("running `{}`", command);
+
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+
+    shell
+        .arg(command)
+        .envs(envs.iter().cloned())
+        .status()
+        .with_context(|| format!("failed to run `{}`", command))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failing_before_run_command_is_an_error() {
+        assert!(before_run("true").is_ok());
+        assert!(before_run("exit 3").is_err());
+    }
+
+    #[test]
+    fn after_run_command_sees_the_exit_reason() {
+        let status = run(
+            "test \"$PROBE_RUN_EXIT_REASON\" = panic",
+            &[(EXIT_REASON_VAR, "panic".to_string())],
+        )
+        .unwrap();
+
+        assert!(status.success());
+    }
+}
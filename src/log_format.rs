@@ -0,0 +1,266 @@
+//! User-defined layout of defmt log lines
+
+use std::{str::FromStr, time::SystemTime};
+
+use anyhow::{anyhow, bail};
+
+/// A log line template like `"{timestamp} {level} {file}:{line} {message}"`
+#[derive(Debug, PartialEq)]
+pub(crate) struct LogFormat {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    Timestamp,
+    Level,
+    File,
+    Line,
+    Module,
+    Message,
+}
+
+/// The parts of a decoded defmt frame that can be placed in a log line
+pub(crate) struct Fields<'a> {
+    pub(crate) timestamp: &'a str,
+    pub(crate) level: &'a str,
+    pub(crate) file: Option<&'a str>,
+    pub(crate) line: Option<u32>,
+    pub(crate) module: Option<&'a str>,
+    pub(crate) message: &'a str,
+}
+
+impl LogFormat {
+    pub(crate) fn format(&self, fields: &Fields) -> String {
+        let mut output = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => output.push_str(literal),
+                Segment::Timestamp => output.push_str(fields.timestamp),
+                Segment::Level => output.push_str(fields.level),
+                Segment::File => output.push_str(fields.file.unwrap_or("?")),
+                Segment::Line => match fields.line {
+                    Some(line) => output.push_str(&line.to_string()),
+                    None => output.push('?'),
+                },
+                Segment::Module => output.push_str(fields.module.unwrap_or("?")),
+                Segment::Message => output.push_str(fields.message),
+            }
+        }
+        output
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = vec![];
+        let mut literal = String::new();
+        let mut chars = s.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest
+                        .find('}')
+                        .ok_or_else(|| anyhow!("unclosed `{{` in log format `{}`", s))?;
+                    let segment = match &rest[..end] {
+                        "timestamp" => Segment::Timestamp,
+                        "level" => Segment::Level,
+                        "file" => Segment::File,
+                        "line" => Segment::Line,
+                        "module" => Segment::Module,
+                        "message" => Segment::Message,
+                        placeholder => bail!(
+                            "unknown placeholder `{{{}}}` in log format; expected one of \
+                            `{{timestamp}}`, `{{level}}`, `{{file}}`, `{{line}}`, `{{module}}` \
+                            or `{{message}}`",
+                            placeholder
+                        ),
+                    };
+                    chars = rest[end + 1..].chars();
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(literal.split_off(0)));
+                    }
+                    segments.push(segment);
+                }
+                '}' => bail!("unmatched `}}` in log format `{}`", s),
+                _ => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self { segments })
+    }
+}
+
+/// How the timestamps of defmt log lines are rendered
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum TimestampFormat {
+    /// As the program formats them, usually a tick count
+    Raw,
+    /// Ticks divided by `--timestamp-freq`, e.g. `12.345s`
+    Seconds,
+    /// The host's time at which the line was received, followed by the device timestamp
+    Iso8601,
+}
+
+impl FromStr for TimestampFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(Self::Raw),
+            "seconds" => Ok(Self::Seconds),
+            "iso8601" => Ok(Self::Iso8601),
+            _ => Err(anyhow!(
+                "invalid timestamp format `{}`; expected `raw`, `seconds` or `iso8601`",
+                s
+            )),
+        }
+    }
+}
+
+impl TimestampFormat {
+    /// Renders the device `timestamp` of a log line received at `now`
+    ///
+    /// Timestamps that are not a tick count are left as they are; so are all timestamps if `freq`
+    /// (in Hz) is not known.
+    pub(crate) fn format(&self, timestamp: &str, freq: Option<u32>, now: SystemTime) -> String {
+        let device = match (self, freq, timestamp.parse::<u64>()) {
+            (Self::Seconds, Some(freq), Ok(ticks)) | (Self::Iso8601, Some(freq), Ok(ticks)) => {
+                ticks_to_seconds(ticks, freq)
+            }
+            _ => timestamp.to_string(),
+        };
+
+        match self {
+            Self::Raw | Self::Seconds => device,
+            Self::Iso8601 if device.is_empty() => humantime::format_rfc3339_millis(now).to_string(),
+            Self::Iso8601 => format!("{} {}", humantime::format_rfc3339_millis(now), device),
+        }
+    }
+}
+
+/// Formats `ticks` of a `freq` Hz clock as seconds with millisecond precision
+fn ticks_to_seconds(ticks: u64, freq: u32) -> String {
+    let freq = u64::from(freq);
+    let millis = u128::from(ticks % freq) * 1000 / u128::from(freq);
+    format!("{}.{:03}s", ticks / freq, millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn formats_all_placeholders() {
+        let format: LogFormat = "{timestamp} [{level}] {module} {file}:{line} {{{message}}}"
+            .parse()
+            .unwrap();
+        let fields = Fields {
+            timestamp: "0.000001",
+            level: "INFO",
+            file: Some("src/main.rs"),
+            line: Some(12),
+            module: Some("app"),
+            message: "hello",
+        };
+
+        assert_eq!(
+            "0.000001 [INFO] app src/main.rs:12 {hello}",
+            format.format(&fields)
+        );
+    }
+
+    #[test]
+    fn missing_location_is_formatted_as_question_mark() {
+        let format: LogFormat = "{file}:{line} {message}".parse().unwrap();
+        let fields = Fields {
+            timestamp: "",
+            level: "WARN",
+            file: None,
+            line: None,
+            module: None,
+            message: "hello",
+        };
+
+        assert_eq!("?:? hello", format.format(&fields));
+    }
+
+    #[test]
+    fn unknown_placeholder_is_rejected() {
+        assert!("{level} {msg}".parse::<LogFormat>().is_err());
+    }
+
+    #[test]
+    fn unclosed_placeholder_is_rejected() {
+        assert!("{level} {message".parse::<LogFormat>().is_err());
+    }
+
+    #[test]
+    fn raw_timestamps_are_unchanged() {
+        let now = SystemTime::UNIX_EPOCH;
+        assert_eq!(
+            "12345",
+            TimestampFormat::Raw.format("12345", Some(1000), now)
+        );
+    }
+
+    #[test]
+    fn ticks_are_converted_to_seconds() {
+        let now = SystemTime::UNIX_EPOCH;
+        assert_eq!(
+            "12.345s",
+            TimestampFormat::Seconds.format("12345", Some(1000), now)
+        );
+        assert_eq!(
+            "0.999s",
+            TimestampFormat::Seconds.format("32767", Some(32768), now)
+        );
+    }
+
+    #[test]
+    fn timestamps_that_are_not_ticks_are_unchanged() {
+        let now = SystemTime::UNIX_EPOCH;
+        assert_eq!(
+            "0.000001",
+            TimestampFormat::Seconds.format("0.000001", Some(1000), now)
+        );
+    }
+
+    #[test]
+    fn iso8601_prepends_the_host_time() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_millis(1_500);
+        assert_eq!(
+            "1970-01-01T00:00:01.500Z 2.000s",
+            TimestampFormat::Iso8601.format("2000", Some(1000), now)
+        );
+        assert_eq!(
+            "1970-01-01T00:00:01.500Z",
+            TimestampFormat::Iso8601.format("", None, now)
+        );
+    }
+
+    #[test]
+    fn unknown_timestamp_format_is_rejected() {
+        assert!("ms".parse::<TimestampFormat>().is_err());
+    }
+}
@@ -1,44 +1,78 @@
 mod backtrace;
 mod canary;
 mod cli;
+mod core_dump;
 mod cortexm;
 mod dep;
+mod dump;
 mod elf;
+mod events;
+mod flash_progress;
+mod global_timeout;
+mod heartbeat;
+mod hooks;
+mod log_format;
 mod probe;
+mod protection;
+mod query;
 mod registers;
+mod relocate;
+mod semihosting;
 mod stacked;
+mod summary;
+mod target_features;
 mod target_info;
+mod uf2;
+mod watchdog;
 
 use std::{
     env, fs,
-    io::{self, Write as _},
+    io::{self, BufRead as _, Write as _},
+    mem,
+    num::NonZeroU32,
+    ops::Range,
     path::Path,
     process,
     sync::atomic::{AtomicBool, Ordering},
-    sync::{Arc, Mutex},
-    time::Duration,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
 use anyhow::{anyhow, bail};
 use colored::Colorize as _;
-use defmt_decoder::Locations;
 use probe_rs::{
-    flashing::{self, Format},
+    flashing::{self, DownloadOptions, Format},
     MemoryInterface as _, Session,
 };
-use probe_rs_rtt::{Rtt, ScanRegion, UpChannel};
+use probe_rs_rtt::{ChannelMode, DownChannel, Rtt, ScanRegion, UpChannel};
+use regex::Regex;
+use serde_json::json;
 use signal_hook::consts::signal;
 
-use crate::{canary::Canary, elf::Elf, target_info::TargetInfo};
+use crate::{
+    canary::Canary, core_dump::CoreDump, elf::Elf, events::Events, log_format::LogFormat,
+    target_info::TargetInfo,
+};
 
 const SIGABRT: i32 = 134;
+/// Exit code of a run whose defmt logs could not be fully decoded
+const EXIT_DECODE_ERROR: i32 = 1;
+/// Exit code of an invocation that `--global-timeout` aborted
+const EXIT_GLOBAL_TIMEOUT: i32 = 124;
+/// Exit code of an invocation whose flashing `--flash-timeout` aborted
+const EXIT_FLASH_TIMEOUT: i32 = 125;
+/// Exit code of a `--expect-panic` run whose program did not panic
+const EXIT_NO_PANIC: i32 = 1;
+/// Exit code of an invocation that failed with an error
+const EXIT_ERROR: i32 = 1;
 const TIMEOUT: Duration = Duration::from_secs(1);
 
 fn main() -> anyhow::Result<()> {
     cli::handle_arguments().map(|code| process::exit(code))
 }
 
-fn run_target_program(elf_path: &Path, chip_name: &str, opts: &cli::Opts) -> anyhow::Result<i32> {
+fn read_elf(elf_path: &Path) -> anyhow::Result<Vec<u8>> {
     if !elf_path.exists() {
         return Err(anyhow!(
             "can't find ELF file at `{}`; are you sure you got the right path?",
@@ -46,11 +80,247 @@ fn run_target_program(elf_path: &Path, chip_name: &str, opts: &cli::Opts) -> any
         ));
     }
 
-    let elf_bytes = fs::read(elf_path)?;
+    Ok(fs::read(elf_path)?)
+}
+
+fn print_symbol_stats(elf: &Elf) {
+    log::info!(
+        "{} functions in the symbol table, {} of them live, {} of those with debug info",
+        elf.num_functions(),
+        elf.live_functions.len(),
+        backtrace::functions_with_debuginfo(elf)
+    );
+}
+
+/// Warns if most of the program can't be symbolicated, e.g. because the debug info is missing
+fn check_debuginfo_coverage(elf: &Elf) {
+    const MIN_COVERAGE: f64 = 0.5;
+
+    if let Some(coverage) = backtrace::debuginfo_coverage(elf) {
+        log::debug!("debug info covers {:.0}% of `.text`", coverage * 100.0);
+
+        if coverage < MIN_COVERAGE {
+            log::warn!(
+                "debug info only covers {:.0}% of `.text`; backtraces will be incomplete. \
+                is the ELF file up to date and compiled with `debug = 1` or higher?",
+                coverage * 100.0
+            );
+        }
+    }
+}
+
+fn dry_run(elf_path: &Path, opts: &cli::Opts) -> anyhow::Result<i32> {
+    let elf_bytes = read_elf(elf_path)?;
+    let elf = &Elf::parse(&elf_bytes)?;
+    check_debuginfo_coverage(elf);
+    if opts.symbol_stats {
+        print_symbol_stats(elf);
+    }
+    if opts.show_target_info {
+        // informational only; a malformed section must not stop the run
+        if let Err(e) = target_features::print(elf) {
+            log::warn!("failed to read the target info from the ELF file: {}", e);
+        }
+    }
+    log::info!("found {} live functions", elf.live_functions.len());
+
+    let current_dir = &env::current_dir()?;
+    let (num_frames, num_with_location) = backtrace::dry_run(elf, current_dir);
+    log::info!(
+        "symbolicated {} frames ({} with location info)",
+        num_frames,
+        num_with_location
+    );
+
+    if num_with_location == 0 {
+        log::warn!("no location info found; backtraces will only contain function names");
+    }
+
+    Ok(0)
+}
+
+fn symbolize_addresses(
+    elf_path: &Path,
+    addresses: &[u32],
+    opts: &cli::Opts,
+) -> anyhow::Result<i32> {
+    let elf_bytes = read_elf(elf_path)?;
     let elf = &Elf::parse(&elf_bytes)?;
 
+    let current_dir = &env::current_dir()?;
+    backtrace::symbolize(elf, addresses, &backtrace_settings(opts, current_dir));
+
+    Ok(0)
+}
+
+fn print_core_dump_backtrace(
+    elf_path: &Path,
+    core_dump_path: &Path,
+    opts: &cli::Opts,
+) -> anyhow::Result<i32> {
+    let elf_bytes = read_elf(elf_path)?;
+    let elf = &Elf::parse(&elf_bytes)?;
+    let mut core_dump = CoreDump::read(core_dump_path)?;
+
+    let current_dir = &env::current_dir()?;
+    let mut backtrace_settings = backtrace_settings(opts, current_dir);
+    // printing the backtrace is the whole point of this mode
+    backtrace_settings.backtrace.force();
+
+    let mut events = Events::create(opts.events_file.as_deref())?;
+    let outcome =
+        backtrace::print(&mut core_dump, elf, &None, &backtrace_settings, &mut events)?.outcome;
+    outcome.log();
+
+    Ok(outcome.into())
+}
+
+fn list_functions_in_range(elf_path: &Path, range: Range<u32>) -> anyhow::Result<i32> {
+    let elf_bytes = read_elf(elf_path)?;
+    let elf = &Elf::parse(&elf_bytes)?;
+
+    backtrace::print_functions_in_range(elf, range);
+
+    Ok(0)
+}
+
+fn dump_line_program(elf_path: &Path, pc: u32) -> anyhow::Result<i32> {
+    let elf_bytes = read_elf(elf_path)?;
+    let elf = &Elf::parse(&elf_bytes)?;
+
+    backtrace::print_line_program(elf, pc)?;
+
+    Ok(0)
+}
+
+fn print_crate_sizes(elf_path: &Path) -> anyhow::Result<i32> {
+    let elf_bytes = read_elf(elf_path)?;
+    let elf = &Elf::parse(&elf_bytes)?;
+
+    backtrace::print_crate_sizes(elf);
+
+    Ok(0)
+}
+
+fn decode_rtt_file(elf_path: &Path, rtt_path: &Path, opts: &cli::Opts) -> anyhow::Result<i32> {
+    let elf_bytes = read_elf(elf_path)?;
+    let elf = &Elf::parse(&elf_bytes)?;
+    if elf.defmt_table.is_none() {
+        bail!("the firmware binary contains no defmt data");
+    }
+
+    let mut buffer = fs::read(rtt_path)?;
+    let current_dir = &env::current_dir()?;
+    let mut defmt_logs = DefmtLogs::default();
+    let mut events = Events::create(opts.events_file.as_deref())?;
+    decode_and_print_defmt_logs(
+        &mut buffer,
+        elf,
+        None,
+        current_dir,
+        opts,
+        &mut defmt_logs,
+        &mut events,
+    )?;
+
+    if !buffer.is_empty() {
+        log::warn!(
+            "{} trailing bytes could not be decoded; the capture may have been cut off mid-frame",
+            buffer.len()
+        );
+    }
+
+    if defmt_logs.decode_errors != 0 {
+        log::error!(
+            "{} malformed defmt frame(s) could not be decoded",
+            defmt_logs.decode_errors
+        );
+        return Ok(EXIT_DECODE_ERROR);
+    }
+
+    Ok(0)
+}
+
+fn run_target_program(elf_path: &Path, chip_name: &str, opts: &cli::Opts) -> anyhow::Result<i32> {
+    let result = flash_and_run(elf_path, chip_name, opts);
+
+    // cleanup hooks are needed most when the run failed, so they also run on errors
+    if let Some(command) = opts.after_run.as_deref() {
+        let (exit_reason, code, error) = match &result {
+            Ok((code, exit_reason)) => (*exit_reason, *code, None),
+            Err(e) => ("error", EXIT_ERROR, Some(format!("{:#}", e))),
+        };
+        if let Err(e) = hooks::after_run(command, exit_reason, code, error.as_deref()) {
+            log::warn!("{:#}", e);
+        }
+    }
+
+    result.map(|(code, _)| code)
+}
+
+/// Exits the process from a timeout thread, while the main thread may be stuck in a probe
+/// operation
+///
+/// `--after-run` still runs, with `error` as the error message, but the probe session is not torn
+/// down: the device is left as it is.
+fn exit_on_timeout(after_run: Option<&str>, exit_reason: &str, code: i32, error: &str) -> ! {
+    if let Some(command) = after_run {
+        if let Err(e) = hooks::after_run(command, exit_reason, code, Some(error)) {
+            log::warn!("{:#}", e);
+        }
+    }
+
+    process::exit(code)
+}
+
+/// Returns the exit code and the exit reason passed to `--after-run`
+fn flash_and_run(
+    elf_path: &Path,
+    chip_name: &str,
+    opts: &cli::Opts,
+) -> anyhow::Result<(i32, &'static str)> {
+    let start = Instant::now();
+    if let Some(timeout) = opts.global_timeout {
+        global_timeout::start(Duration::from_secs(timeout), opts.after_run.clone());
+    }
+
+    let image_bytes = read_elf(elf_path)?;
+    let uf2_blocks = if uf2::is_uf2(&image_bytes) {
+        Some(uf2::parse(&image_bytes)?)
+    } else {
+        None
+    };
+
+    let elf_bytes = match (&uf2_blocks, opts.debug_file.as_deref()) {
+        (Some(_), Some(debug_file)) => read_elf(debug_file)?,
+        (Some(_), None) => bail!(
+            "`{}` is a UF2 image, which contains no debug info; \
+            pass the matching ELF file with `--debug-file`",
+            elf_path.display()
+        ),
+        (None, _) => image_bytes,
+    };
+    if uf2_blocks.is_some() && opts.flash_offset.is_some() {
+        bail!("`--flash-offset` can't be used with UF2 images, which carry absolute addresses");
+    }
+    let elf = &Elf::parse(&elf_bytes)?;
+    check_debuginfo_coverage(elf);
+    if opts.symbol_stats {
+        print_symbol_stats(elf);
+    }
+    if opts.show_target_info {
+        // informational only; a malformed section must not stop the run
+        if let Err(e) = target_features::print(elf) {
+            log::warn!("failed to read the target info from the ELF file: {}", e);
+        }
+    }
+
     let target_info = TargetInfo::new(chip_name, elf)?;
 
+    if let Some(command) = opts.before_run.as_deref() {
+        hooks::before_run(command)?;
+    }
+
     let probe = probe::open(opts)?;
 
     let probe_target = target_info.probe_target.clone();
@@ -61,55 +331,369 @@ fn run_target_program(elf_path: &Path, chip_name: &str, opts: &cli::Opts) -> any
     };
     log::debug!("started session");
 
+    let mut events = Events::create(opts.events_file.as_deref())?;
+
+    global_timeout::enter(global_timeout::Phase::Flashing);
     if opts.no_flash {
         log::info!("skipped flashing");
     } else {
-        let size = elf.program_flash_size();
+        let size = match &uf2_blocks {
+            Some(blocks) => blocks.iter().map(|block| block.data.len() as u64).sum(),
+            None => elf.program_flash_size(),
+        };
         log::info!("flashing program ({:.02} KiB)", size as f64 / 1024.0);
 
-        flashing::download_file(&mut sess, elf_path, Format::Elf)?;
+        if let Some(offset) = opts.flash_offset {
+            log::info!("flashing at an offset of {:#x}", offset);
+        }
+
+        let image = Image {
+            elf_path,
+            elf_bytes: &elf_bytes,
+            uf2_blocks: uf2_blocks.as_deref(),
+        };
+        if let Err(e) = flash(&mut sess, &image, opts) {
+            // mass-erasing is only the answer if the chip refused to be flashed because it is locked
+            if protection::is_locked(&mut sess, chip_name) != Some(true) {
+                return Err(e);
+            }
+
+            if !opts.chip_erase_if_locked {
+                return Err(e.context(
+                    "failed to flash the program because the chip is read-out protected (locked); \
+                    re-run with `--chip-erase-if-locked` to mass-erase and unlock it",
+                ));
+            }
+
+            log::warn!("flashing failed: {:?}", e);
+            log::warn!(
+                "the chip is locked; MASS-ERASING IT as requested by `--chip-erase-if-locked`. \
+                this wipes ALL of the device's flash"
+            );
+            flashing::erase_all(&mut sess)?;
+            flash(&mut sess, &image, opts)?;
+        }
         log::info!("success!");
+        events.emit("flash", json!({ "size": size }))?;
     }
 
-    let canary = Canary::install(&mut sess, &target_info, elf)?;
-    start_program(&mut sess, elf)?;
+    if opts.flash_only {
+        return Ok((0, "flashed"));
+    }
+
+    global_timeout::enter(global_timeout::Phase::Running);
+    if opts.pre_run_reset == cli::PreRunReset::Hard {
+        sess = hard_reset(sess, &target_info, opts)?;
+    }
+    pre_run_reset(&mut sess, opts.pre_run_reset)?;
+    if opts.disable_watchdog {
+        watchdog::disable(&mut sess, chip_name)?;
+    }
+    // the stack of a program that keeps running may already reach into the canary
+    let canary = if opts.pre_run_reset == cli::PreRunReset::None {
+        None
+    } else {
+        Canary::install(&mut sess, &target_info, elf)?
+    };
+    start_program(&mut sess, elf, opts.rtt_mode, opts.pre_run_reset)?;
 
     let sess = Arc::new(Mutex::new(sess));
+    if opts.list_rtt_channels {
+        return list_rtt_channels(elf, sess, opts).map(|code| (code, "ok"));
+    }
     let current_dir = &env::current_dir()?;
 
-    let halted_due_to_signal = extract_and_print_logs(elf, &sess, opts, current_dir)?;
+    let (logs_end, defmt_logs) =
+        extract_and_print_logs(elf, &sess, opts, current_dir, &mut events)?;
 
-    print_separator();
+    print_separator(opts.quiet);
+    global_timeout::enter(global_timeout::Phase::TearingDown);
 
     let mut sess = sess.lock().unwrap();
     let mut core = sess.core(0)?;
 
-    let canary_touched = canary
-        .map(|canary| canary.touched(&mut core, elf))
+    let canary_overflowed = canary
+        .map(|canary| canary.overflowed(&mut core, elf, opts.canary_threshold))
         .transpose()?
         .unwrap_or(false);
-    let backtrace_settings = backtrace::Settings {
-        current_dir,
-        max_backtrace_len: opts.max_backtrace_len,
-        force_backtrace: opts.force_backtrace || canary_touched || halted_due_to_signal,
-        shorten_paths: opts.shorten_paths,
+    // `panic-probe` logs the panic message and then executes `BKPT`
+    let panic_message = match &defmt_logs.panic_message {
+        Some(message) if logs_end == LogsEnd::DeviceHalted && halted_on_breakpoint(&mut core)? => {
+            Some(message)
+        }
+        _ => None,
     };
 
-    let outcome = backtrace::print(
+    let mut backtrace_settings = backtrace_settings(opts, current_dir);
+    if canary_overflowed || logs_end == LogsEnd::HaltedByHost || panic_message.is_some() {
+        backtrace_settings.backtrace.force();
+    }
+
+    let report = backtrace::print(
         &mut core,
         elf,
         &target_info.active_ram_region,
         &backtrace_settings,
+        &mut events,
     )?;
+    let mut outcome = report.outcome;
+    if let (Some(message), backtrace::Outcome::Ok) = (panic_message, outcome) {
+        log::error!("{}", message);
+        outcome = backtrace::Outcome::Panic;
+    }
 
-    core.reset_and_halt(TIMEOUT)?;
+    for read in &opts.read_symbol {
+        dump::print(&mut core, elf, read)?;
+    }
 
-    outcome.log();
+    let abnormal = outcome != backtrace::Outcome::Ok || logs_end == LogsEnd::HaltedByHost;
+    if opts.leave_halted && abnormal {
+        let pc = core.read_core_reg(registers::PC)?;
+        log::info!(
+            "leaving the core halted at PC {:#010x} so that a debugger can attach",
+            pc
+        );
+    } else {
+        core.reset_and_halt(TIMEOUT)?;
+    }
 
-    Ok(outcome.into())
+    let code = if logs_end == LogsEnd::TerminateMatched {
+        log::info!("a log line matched `--terminate-on`");
+        opts.terminate_code
+    } else {
+        outcome.log();
+
+        if defmt_logs.decode_errors != 0 {
+            log::error!(
+                "{} malformed defmt frame(s) could not be decoded",
+                defmt_logs.decode_errors
+            );
+        }
+
+        if opts.expect_panic {
+            expected_panic_code(outcome)
+        } else if defmt_logs.decode_errors != 0 && outcome == backtrace::Outcome::Ok {
+            EXIT_DECODE_ERROR
+        } else {
+            outcome.into()
+        }
+    };
+    events.emit(
+        "exit",
+        json!({ "outcome": format!("{:?}", outcome), "code": code }),
+    )?;
+
+    let exit_reason = exit_reason(logs_end, outcome, &defmt_logs);
+
+    if opts.summary && !opts.quiet {
+        let summary = summary::Summary {
+            exit: exit_reason,
+            frames: report.frames,
+            num_logs: defmt_logs.num_lines,
+            duration: start.elapsed(),
+        };
+        eprintln!("{}", summary);
+    }
+
+    Ok((code, exit_reason))
+}
+
+/// Exit code of a `--expect-panic` run: success only if the program panicked
+fn expected_panic_code(outcome: backtrace::Outcome) -> i32 {
+    match outcome {
+        backtrace::Outcome::HardFault | backtrace::Outcome::Panic => {
+            log::info!("the program panicked as expected (`--expect-panic`)");
+            0
+        }
+        backtrace::Outcome::Ok | backtrace::Outcome::StackOverflow => {
+            log::error!("the program was expected to panic but did not (`--expect-panic`)");
+            EXIT_NO_PANIC
+        }
+    }
+}
+
+/// Why the run ended, as reported by `--summary` and to `--after-run`
+fn exit_reason(
+    logs_end: LogsEnd,
+    outcome: backtrace::Outcome,
+    defmt_logs: &DefmtLogs,
+) -> &'static str {
+    match (logs_end, outcome) {
+        (LogsEnd::TerminateMatched, _) => "terminate-on",
+        (_, backtrace::Outcome::HardFault) => "hardfault",
+        (_, backtrace::Outcome::Panic) => "panic",
+        (_, backtrace::Outcome::StackOverflow) => "stack-overflow",
+        (LogsEnd::HaltedByHost, backtrace::Outcome::Ok) => "halted-by-host",
+        (LogsEnd::DeviceHalted, backtrace::Outcome::Ok) if defmt_logs.decode_errors != 0 => {
+            "decode-error"
+        }
+        (LogsEnd::DeviceHalted, backtrace::Outcome::Ok) => "ok",
+    }
+}
+
+/// The program image to write to flash
+struct Image<'a> {
+    elf_path: &'a Path,
+    elf_bytes: &'a [u8],
+    /// Set if the image is a UF2 file, in which case `elf_bytes` only carry its debug info
+    uf2_blocks: Option<&'a [uf2::Block]>,
+}
+
+fn flash(sess: &mut Session, image: &Image, opts: &cli::Opts) -> anyhow::Result<()> {
+    let watchdog = opts.flash_timeout.map(|timeout| {
+        flash_progress::Watchdog::start(Duration::from_secs(timeout), opts.after_run.clone())
+    });
+    let show_progress = !opts.no_progress && !opts.quiet;
+    let progress = flash_progress::new(
+        atty::is(atty::Stream::Stderr),
+        show_progress,
+        watchdog.clone(),
+    );
+    let options = DownloadOptions {
+        progress: if show_progress || watchdog.is_some() {
+            Some(&progress)
+        } else {
+            None
+        },
+        ..DownloadOptions::default()
+    };
+
+    let result = if let Some(blocks) = image.uf2_blocks {
+        uf2::flash(sess, blocks, options)
+    } else if let Some(offset) = opts.flash_offset {
+        relocate::flash(sess, image.elf_bytes, offset, options)
+    } else {
+        flashing::download_file_with_options(sess, image.elf_path, Format::Elf, options)
+            .map_err(anyhow::Error::from)
+    };
+
+    if let Some(watchdog) = watchdog {
+        watchdog.stop();
+    }
+
+    result
+}
+
+/// Returns `true` if the halted core sits on a `BKPT` instruction other than a semihosting trap
+fn halted_on_breakpoint(core: &mut probe_rs::Core) -> anyhow::Result<bool> {
+    let pc = core.read_core_reg(registers::PC)?;
+    let mut instruction = [0; 2];
+    core.read_8(pc, &mut instruction)?;
+
+    Ok(cortexm::is_breakpoint(u16::from_le_bytes(instruction)))
+}
+
+/// Moves the halted `core` past the `BKPT` it stopped at
+///
+/// Returns `false` if the core halted anywhere else
+fn skip_breakpoint(core: &mut probe_rs::Core) -> anyhow::Result<bool> {
+    if !halted_on_breakpoint(core)? {
+        return Ok(false);
+    }
+
+    let pc = core.read_core_reg(registers::PC)?;
+    log::debug!("continuing past the `BKPT` at {:#010x}", pc);
+    core.write_core_reg(registers::PC, pc + cortexm::BKPT_SIZE)?;
+
+    Ok(true)
+}
+
+/// What the host learned from the logs printed so far
+#[derive(Default)]
+struct DefmtLogs {
+    /// Number of printed log lines: decoded defmt frames or lines of raw output
+    num_lines: usize,
+    /// Number of runs of malformed data that had to be skipped
+    decode_errors: usize,
+    /// Message of the last decoded frame, if that frame reported a panic
+    panic_message: Option<String>,
+}
+
+/// Why the host stopped printing the target's logs
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LogsEnd {
+    /// The device halted on its own, e.g. due to a breakpoint or HardFault
+    DeviceHalted,
+    /// The host halted the device, e.g. due to Ctrl-C or because RTT never came up
+    HaltedByHost,
+    /// A log line matched `--terminate-on`; the host halted the device
+    TerminateMatched,
+}
+
+fn backtrace_settings<'p>(opts: &'p cli::Opts, current_dir: &'p Path) -> backtrace::Settings<'p> {
+    backtrace::Settings {
+        current_dir,
+        max_backtrace_len: opts.max_backtrace_len,
+        backtrace: if opts.force_backtrace {
+            backtrace::BacktraceOptions::Always
+        } else {
+            opts.backtrace
+        },
+        shorten_paths: opts.shorten_paths,
+        no_shorten_paths: opts.no_shorten_paths,
+        path_rules: &opts.path_rules,
+        backtrace_debug: opts.backtrace_debug,
+        backtrace_reverse: opts.backtrace_reverse,
+        hide_builtins: opts.backtrace_hide_builtins,
+        self_only: opts.backtrace_self_only,
+        keep: opts.backtrace_keep.as_ref(),
+        max_inline_frames: opts.max_inline_frames,
+        backtrace_style: opts.backtrace_style,
+        skip: opts.backtrace_skip,
+        keep_numbers: opts.backtrace_keep_numbers,
+        frame_numbers: opts.backtrace_frame_numbers,
+        show_ranges: opts.backtrace_ranges,
+        signatures: opts.backtrace_signatures,
+        open_editor: opts.open_editor,
+        load_base: opts.load_base,
+        #[cfg(feature = "disasm")]
+        disasm_on_fault: opts.disasm_on_fault,
+    }
+}
+
+/// Pulls the nRST pin by attaching to the target under reset
+///
+/// The session owns the probe, so it is closed and the probe is opened again.
+fn hard_reset(
+    sess: Session,
+    target_info: &TargetInfo,
+    opts: &cli::Opts,
+) -> anyhow::Result<Session> {
+    drop(sess);
+
+    let probe = probe::open(opts)?;
+    match probe.attach_under_reset(target_info.probe_target.clone()) {
+        Ok(sess) => Ok(sess),
+        Err(probe_rs::Error::Probe(probe_rs::DebugProbeError::NotImplemented(_))) => {
+            bail!("hard reset not supported by this probe; use `--pre-run-reset soft` instead")
+        }
+        Err(e) => Err(anyhow!(e).context("failed to reset the device through the nRST pin")),
+    }
 }
 
-fn start_program(sess: &mut Session, elf: &Elf) -> Result<(), anyhow::Error> {
+/// Brings the core into a halted state, ready to run the program
+///
+/// After a hard reset, the core is halted at the reset vector as after a soft one.
+fn pre_run_reset(sess: &mut Session, reset: cli::PreRunReset) -> anyhow::Result<()> {
+    let mut core = sess.core(0)?;
+    match reset {
+        cli::PreRunReset::Hard | cli::PreRunReset::Soft => {
+            core.reset_and_halt(TIMEOUT)?;
+        }
+        cli::PreRunReset::None => {
+            core.halt(TIMEOUT)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn start_program(
+    sess: &mut Session,
+    elf: &Elf,
+    rtt_mode: cli::RttMode,
+    pre_run_reset: cli::PreRunReset,
+) -> Result<(), anyhow::Error> {
     let mut core = sess.core(0)?;
 
     log::debug!("starting device");
@@ -121,16 +705,21 @@ fn start_program(sess: &mut Session, elf: &Elf) -> Result<(), anyhow::Error> {
         }
     }
 
-    if let Some(rtt) = elf.rtt_buffer_address() {
-        let main = elf.main_fn_address();
-        core.set_hw_breakpoint(main)?;
-        core.run()?;
-        core.wait_for_core_halted(Duration::from_secs(5))?;
+    // with `--rtt-mode non-blocking` the channel keeps the mode the program configured
+    if let (Some(rtt), cli::RttMode::Blocking) = (elf.rtt_buffer_address(), rtt_mode) {
+        // the program sets up RTT before `main`; without a reset it was halted past that point
+        // and would never reach the breakpoint
+        if pre_run_reset != cli::PreRunReset::None {
+            let main = elf.main_fn_address();
+            core.set_hw_breakpoint(main)?;
+            core.run()?;
+            core.wait_for_core_halted(Duration::from_secs(5))?;
+            core.clear_hw_breakpoint(main)?;
+        }
 
         const OFFSET: u32 = 44;
         const FLAG: u32 = 2; // BLOCK_IF_FULL
         core.write_word_32(rtt + OFFSET, FLAG)?;
-        core.clear_hw_breakpoint(main)?;
     }
 
     core.set_hw_breakpoint(cortexm::clear_thumb_bit(elf.vector_table.hard_fault))?;
@@ -144,20 +733,45 @@ fn extract_and_print_logs(
     sess: &Arc<Mutex<Session>>,
     opts: &cli::Opts,
     current_dir: &Path,
-) -> Result<bool, anyhow::Error> {
-    let exit = Arc::new(AtomicBool::new(false));
-    let sig_id = signal_hook::flag::register(signal::SIGINT, exit.clone())?;
-
-    let mut logging_channel = if let Some(address) = elf.rtt_buffer_address() {
-        Some(setup_logging_channel(address, sess.clone())?)
+    events: &mut Events,
+) -> Result<(LogsEnd, DefmtLogs), anyhow::Error> {
+    let (up_channels, mut down_channel) = if let Some(address) = elf.rtt_buffer_address() {
+        let timeout = Duration::from_millis(opts.rtt_attach_timeout);
+        if let Some(channels) = setup_logging_channels(
+            address,
+            sess.clone(),
+            timeout,
+            opts.rtt_up_channels,
+            opts.rtt_down_channel,
+        )? {
+            channels
+        } else {
+            // a run without logs must not pass for a successful one
+            sess.lock().unwrap().core(0)?.halt(TIMEOUT)?;
+            bail!(
+                "RTT control block was not initialized within {} ms; halted the device",
+                opts.rtt_attach_timeout
+            );
+        }
     } else {
-        eprintln!("RTT logs not available; blocking until the device halts..");
-        None
+        if !opts.quiet {
+            eprintln!("RTT logs not available; blocking until the device halts..");
+        }
+        (vec![], None)
     };
+    let input = down_channel.as_ref().map(|_| spawn_stdin_reader());
+    let mut pending_input = vec![];
+
+    let exit = Arc::new(AtomicBool::new(false));
+    let sig_id = signal_hook::flag::register(signal::SIGINT, exit.clone())?;
 
-    let use_defmt = logging_channel
-        .as_ref()
-        .map_or(false, |channel| channel.name() == Some("defmt"));
+    // only tell the channels apart if there's more than one
+    let show_prefix = up_channels.len() > 1;
+    let mut log_channels = up_channels
+        .into_iter()
+        .map(|channel| LogChannel::new(channel, show_prefix, opts.rtt_mode))
+        .collect::<Vec<_>>();
+    let use_defmt = log_channels.iter().any(|channel| channel.is_defmt);
 
     if use_defmt && opts.no_flash {
         bail!(
@@ -167,56 +781,92 @@ fn extract_and_print_logs(
         bail!("\"defmt\" RTT channel is in use, but the firmware binary contains no defmt data");
     }
 
-    print_separator();
+    print_separator(opts.quiet);
 
-    let stdout = io::stdout();
-    let mut stdout = stdout.lock();
     let mut read_buf = [0; 1024];
-    let mut defmt_buffer = vec![];
+    let mut defmt_logs = DefmtLogs::default();
     let mut was_halted = false;
-    while !exit.load(Ordering::Relaxed) {
-        if let Some(logging_channel) = &mut logging_channel {
-            let num_bytes_read = match logging_channel.read(&mut read_buf) {
-                Ok(n) => n,
-                Err(e) => {
-                    eprintln!("RTT error: {}", e);
-                    break;
-                }
-            };
-
-            if num_bytes_read != 0 {
-                match &elf.defmt_table {
-                    Some(table) if use_defmt => {
-                        defmt_buffer.extend_from_slice(&read_buf[..num_bytes_read]);
-
-                        decode_and_print_defmt_logs(
-                            &mut defmt_buffer,
-                            table,
-                            elf.defmt_locations.as_ref(),
-                            current_dir,
-                            opts,
-                        )?;
+    let mut terminated = false;
+    let mut heartbeat = heartbeat::Heartbeat::new(
+        !opts.quiet && atty::is(atty::Stream::Stdout) && atty::is(atty::Stream::Stderr),
+    );
+    'logs: while !exit.load(Ordering::Relaxed) {
+        if !log_channels.is_empty() {
+            // read at most one buffer from each channel per round, so that a busy channel can't
+            // starve the others
+            let mut num_bytes_read = 0;
+            let mut matched = false;
+            for log_channel in &mut log_channels {
+                let num_channel_bytes_read = match log_channel.channel.read(&mut read_buf) {
+                    Ok(n) => n,
+                    Err(e) => {
+                        log::error!("RTT error: {}", e);
+                        break 'logs;
                     }
+                };
 
-                    _ => {
-                        stdout.write_all(&read_buf[..num_bytes_read])?;
-                        stdout.flush()?;
-                    }
+                if num_channel_bytes_read != 0 {
+                    heartbeat.clear();
+                    num_bytes_read += num_channel_bytes_read;
+                    matched |= log_channel.print(
+                        &read_buf[..num_channel_bytes_read],
+                        elf,
+                        current_dir,
+                        opts,
+                        &mut defmt_logs,
+                        events,
+                    )?;
                 }
             }
+
+            // all logs buffered before the device was halted have been printed
+            if terminated && num_bytes_read == 0 {
+                break;
+            }
+
+            if matched && !terminated {
+                terminated = true;
+
+                let mut sess = sess.lock().unwrap();
+                sess.core(0)?.halt(TIMEOUT)?;
+            }
+
+            if terminated {
+                continue;
+            }
+        }
+        heartbeat.tick(defmt_logs.num_lines);
+
+        if let (Some(down_channel), Some(input)) = (&mut down_channel, &input) {
+            pending_input.extend(input.try_iter().flatten());
+            if !pending_input.is_empty() {
+                let num_bytes_written = down_channel.write(&pending_input)?;
+                pending_input.drain(..num_bytes_written);
+            }
         }
 
         let mut sess = sess.lock().unwrap();
         let mut core = sess.core(0)?;
         let is_halted = core.core_halted()?;
 
+        if is_halted && semihosting::serve(&mut core, &opts.env)? == semihosting::Request::Served {
+            core.run()?;
+            was_halted = false;
+            continue;
+        }
+
+        if is_halted && opts.ignore_breakpoints && skip_breakpoint(&mut core)? {
+            core.run()?;
+            was_halted = false;
+            continue;
+        }
+
         if is_halted && was_halted {
             break;
         }
         was_halted = is_halted;
     }
-
-    drop(stdout);
+    heartbeat.clear();
 
     signal_hook::low_level::unregister(sig_id);
     signal_hook::flag::register_conditional_default(signal::SIGINT, exit.clone())?;
@@ -229,21 +879,199 @@ fn extract_and_print_logs(
         core.halt(TIMEOUT)?;
     }
 
-    let halted_due_to_signal = exit.load(Ordering::Relaxed);
+    let logs_end = if exit.load(Ordering::Relaxed) {
+        LogsEnd::HaltedByHost
+    } else if terminated {
+        LogsEnd::TerminateMatched
+    } else {
+        LogsEnd::DeviceHalted
+    };
 
-    Ok(halted_due_to_signal)
+    Ok((logs_end, defmt_logs))
 }
 
+/// Warns if an up channel is not in the mode `--rtt-mode` says it is expected in
+fn warn_about_rtt_mode(number: usize, is_blocking: bool, expected_mode: cli::RttMode) {
+    match (is_blocking, expected_mode) {
+        (true, cli::RttMode::NonBlocking) => log::warn!(
+            "RTT up channel {} is in blocking mode: the program stalls whenever the channel is \
+             full, which distorts its timing, and hangs if nothing reads from the channel",
+            number
+        ),
+        (false, cli::RttMode::Blocking) => log::warn!(
+            "RTT up channel {} is not in blocking mode: logs are lost whenever the channel is full",
+            number
+        ),
+        _ => {}
+    }
+}
+
+/// An RTT up channel whose output is printed
+struct LogChannel {
+    channel: UpChannel,
+    is_defmt: bool,
+    /// Put in front of each printed line when several channels are shown
+    prefix: Option<String>,
+    /// defmt data that has not been decoded yet
+    defmt_buffer: Vec<u8>,
+    /// Raw output after the last complete line
+    line_buffer: String,
+}
+
+impl LogChannel {
+    fn new(channel: UpChannel, show_prefix: bool, expected_mode: cli::RttMode) -> Self {
+        let prefix = if show_prefix {
+            Some(match channel.name() {
+                Some(name) => format!("[{}:{}] ", channel.number(), name),
+                None => format!("[{}] ", channel.number()),
+            })
+        } else {
+            None
+        };
+
+        let is_blocking = match channel.mode() {
+            Ok(mode) => matches!(mode, ChannelMode::BlockIfFull),
+            Err(e) => {
+                log::debug!(
+                    "failed to read the mode of RTT up channel {}: {}",
+                    channel.number(),
+                    e
+                );
+                expected_mode == cli::RttMode::Blocking
+            }
+        };
+        warn_about_rtt_mode(channel.number(), is_blocking, expected_mode);
+
+        Self {
+            is_defmt: channel.name() == Some("defmt"),
+            channel,
+            prefix,
+            defmt_buffer: vec![],
+            line_buffer: String::new(),
+        }
+    }
+
+    /// Prints the `bytes` read from the channel
+    ///
+    /// Returns `true` if a printed line matched `--terminate-on`
+    fn print(
+        &mut self,
+        bytes: &[u8],
+        elf: &Elf,
+        current_dir: &Path,
+        opts: &cli::Opts,
+        defmt_logs: &mut DefmtLogs,
+        events: &mut Events,
+    ) -> anyhow::Result<bool> {
+        if self.is_defmt && elf.defmt_table.is_some() {
+            self.defmt_buffer.extend_from_slice(bytes);
+
+            return decode_and_print_defmt_logs(
+                &mut self.defmt_buffer,
+                elf,
+                self.prefix.as_deref(),
+                current_dir,
+                opts,
+                defmt_logs,
+                events,
+            );
+        }
+
+        defmt_logs.num_lines += bytes.iter().filter(|&&byte| byte == b'\n').count();
+        events.emit(
+            "output",
+            json!({
+                "channel": self.channel.number(),
+                "data": String::from_utf8_lossy(bytes)
+            }),
+        )?;
+
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        if let Some(prefix) = &self.prefix {
+            return print_prefixed_lines(
+                &mut self.line_buffer,
+                bytes,
+                prefix,
+                opts.terminate_on.as_ref(),
+                &mut stdout,
+            );
+        }
+
+        stdout.write_all(bytes)?;
+        stdout.flush()?;
+
+        Ok(match_raw_lines(
+            &mut self.line_buffer,
+            bytes,
+            opts.terminate_on.as_ref(),
+        ))
+    }
+}
+
+/// Prints each completed line of raw RTT output after `prefix` and checks it against
+/// `terminate_on`
+///
+/// Incomplete lines are kept in `line_buffer` until the rest of them arrives
+fn print_prefixed_lines(
+    line_buffer: &mut String,
+    bytes: &[u8],
+    prefix: &str,
+    terminate_on: Option<&Regex>,
+    stdout: &mut impl io::Write,
+) -> anyhow::Result<bool> {
+    line_buffer.push_str(&String::from_utf8_lossy(bytes));
+
+    let mut matched = false;
+    while let Some(end) = line_buffer.find('\n') {
+        let line = line_buffer.drain(..=end).collect::<String>();
+        write!(stdout, "{}{}", prefix.dimmed(), line)?;
+        matched |= terminate_on.map_or(false, |regex| regex.is_match(line.trim_end()));
+    }
+    stdout.flush()?;
+
+    Ok(matched)
+}
+
+/// Appends raw RTT output to `line_buffer` and checks each completed line against `terminate_on`
+fn match_raw_lines(line_buffer: &mut String, bytes: &[u8], terminate_on: Option<&Regex>) -> bool {
+    let regex = match terminate_on {
+        Some(regex) => regex,
+        None => return false,
+    };
+
+    line_buffer.push_str(&String::from_utf8_lossy(bytes));
+
+    let mut matched = false;
+    while let Some(end) = line_buffer.find('\n') {
+        let line = line_buffer.drain(..=end).collect::<String>();
+        matched |= regex.is_match(line.trim_end());
+    }
+    matched
+}
+
+/// Decodes and prints the complete defmt frames in `buffer`, each after `prefix` if given
+///
+/// `elf` must contain a defmt table.
 fn decode_and_print_defmt_logs(
     buffer: &mut Vec<u8>,
-    table: &defmt_decoder::Table,
-    locations: Option<&Locations>,
+    elf: &Elf,
+    prefix: Option<&str>,
     current_dir: &Path,
     opts: &cli::Opts,
-) -> Result<(), anyhow::Error> {
+    defmt_logs: &mut DefmtLogs,
+    events: &mut Events,
+) -> Result</* matched `--terminate-on`: */ bool, anyhow::Error> {
+    // NOTE(unwrap) callers check that the program contains defmt data before decoding any
+    let table = elf.defmt_table.as_ref().unwrap();
+    let locations = elf.defmt_locations.as_ref();
+    let mut matched = false;
+    let mut skipped = 0;
     loop {
         match table.decode(buffer) {
             Ok((frame, consumed)) => {
+                print_skipped_bytes(mem::take(&mut skipped));
+
                 // NOTE(`[]` indexing) all indices in `table` have already been verified to exist in
                 // the `locations` map
                 let (file, line, mod_path) = locations
@@ -251,11 +1079,13 @@ fn decode_and_print_defmt_logs(
                     .map(|location| {
                         let path = if let Ok(relpath) = location.file.strip_prefix(&current_dir) {
                             relpath.display().to_string()
+                        } else if opts.no_shorten_paths {
+                            location.file.display().to_string()
                         } else {
                             let dep_path = dep::Path::from_std_path(&location.file);
 
                             if opts.shorten_paths {
-                                dep_path.format_short()
+                                dep_path.format_short(&opts.path_rules)
                             } else {
                                 dep_path.format_highlight()
                             }
@@ -269,8 +1099,43 @@ fn decode_and_print_defmt_logs(
                     })
                     .unwrap_or((None, None, None));
 
-                // Forward the defmt frame to our logger.
-                defmt_decoder::log::log_defmt(&frame, file.as_deref(), line, mod_path);
+                let location = locations.map(|locations| &locations[&frame.index()]);
+                events.emit(
+                    "log",
+                    json!({
+                        "level": format!("{:?}", frame.level()).to_lowercase(),
+                        "timestamp": frame.display_timestamp().map(|ts| ts.to_string()),
+                        "message": frame.display_message().to_string(),
+                        "file": location.map(|location| location.file.display().to_string()),
+                        "line": location.map(|location| location.line),
+                        "module": location.map(|location| &location.module),
+                    }),
+                )?;
+
+                let timestamp = frame
+                    .display_timestamp()
+                    .map(|timestamp| timestamp.to_string())
+                    .unwrap_or_default();
+                let timestamp = opts.log_timestamp_format.format(
+                    &timestamp,
+                    opts.timestamp_freq.map(NonZeroU32::get),
+                    SystemTime::now(),
+                );
+                let line = print_defmt_frame(
+                    &frame,
+                    &timestamp,
+                    prefix,
+                    file.as_deref(),
+                    line,
+                    mod_path,
+                    opts.log_format.as_ref(),
+                );
+                matched |= opts
+                    .terminate_on
+                    .as_ref()
+                    .map_or(false, |regex| regex.is_match(&line));
+                defmt_logs.panic_message = panic_message(&frame);
+                defmt_logs.num_lines += 1;
 
                 let num_bytes = buffer.len();
                 buffer.rotate_left(consumed);
@@ -279,34 +1144,147 @@ fn decode_and_print_defmt_logs(
 
             Err(defmt_decoder::DecodeError::UnexpectedEof) => break,
 
+            // defmt data has no frame delimiters; skip ahead one byte at a time until a frame
+            // decodes again
             Err(defmt_decoder::DecodeError::Malformed) => {
-                log::error!("failed to decode defmt data: {:x?}", buffer);
-                return Err(defmt_decoder::DecodeError::Malformed.into());
+                if skipped == 0 {
+                    log::debug!("failed to decode defmt data: {:x?}", buffer);
+                    defmt_logs.decode_errors += 1;
+                }
+                buffer.remove(0);
+                skipped += 1;
             }
         }
     }
 
-    Ok(())
+    print_skipped_bytes(skipped);
+
+    Ok(matched)
+}
+
+fn print_skipped_bytes(skipped: usize) {
+    if skipped != 0 {
+        println!(
+            "{}",
+            format!("<defmt decode error: skipped {} malformed bytes>", skipped).red()
+        );
+    }
+}
+
+/// Returns the frame's message if it is the one `panic-probe` logs when the program panics
+fn panic_message(frame: &defmt_decoder::Frame) -> Option<String> {
+    let message = frame.display_message().to_string();
+    if frame.level() == defmt_decoder::Level::Error && message.starts_with("panicked at") {
+        Some(message)
+    } else {
+        None
+    }
+}
+
+/// Prints a decoded defmt frame, colored according to its log level, followed by its location
+///
+/// Returns the printed line without color
+fn print_defmt_frame(
+    frame: &defmt_decoder::Frame,
+    timestamp: &str,
+    prefix: Option<&str>,
+    file: Option<&str>,
+    line: Option<u32>,
+    mod_path: Option<&str>,
+    log_format: Option<&LogFormat>,
+) -> String {
+    let level = frame.level();
+    let level_str = format!("{:?}", level).to_uppercase();
+    let message = frame.display_message().to_string();
+
+    let message = if let Some(log_format) = log_format {
+        log_format.format(&log_format::Fields {
+            timestamp,
+            level: &level_str,
+            file,
+            line,
+            module: mod_path,
+            message: &message,
+        })
+    } else {
+        format!("{} {:<5} {}", timestamp, level_str, message)
+    };
+
+    let colored_message = match level {
+        defmt_decoder::Level::Error => message.red(),
+        defmt_decoder::Level::Warn => message.yellow(),
+        defmt_decoder::Level::Info => message.normal(),
+        defmt_decoder::Level::Debug | defmt_decoder::Level::Trace => message.dimmed(),
+    };
+    let prefix = prefix.unwrap_or("");
+    println!("{}{}", prefix.dimmed(), colored_message);
+
+    // a custom format places the location itself
+    if log_format.is_some() {
+        return message;
+    }
+
+    if let (Some(file), Some(line), Some(mod_path)) = (file, line, mod_path) {
+        println!(
+            "{}{}",
+            prefix.dimmed(),
+            format!("└─ {} @ {}:{}", mod_path, file, line).dimmed()
+        );
+    }
+
+    message
 }
 
-fn setup_logging_channel(
+/// Returns `None` if the RTT control block was not initialized within `timeout`
+///
+/// The down channel is `None` if the target has no down channel with the given number
+fn setup_logging_channels(
     rtt_buffer_address: u32,
     sess: Arc<Mutex<Session>>,
-) -> anyhow::Result<UpChannel> {
-    const NUM_RETRIES: usize = 10; // picked at random, increase if necessary
+    timeout: Duration,
+    up_channels: cli::RttUpChannels,
+    down_channel: Option<usize>,
+) -> anyhow::Result<Option<(Vec<UpChannel>, Option<DownChannel>)>> {
+    let mut rtt = match attach_rtt(rtt_buffer_address, sess, timeout)? {
+        Some(rtt) => rtt,
+        None => return Ok(None),
+    };
 
+    let channels = match up_channels {
+        cli::RttUpChannels::One(number) => vec![rtt
+            .up_channels()
+            .take(number)
+            .ok_or_else(|| anyhow!("RTT up channel {} not found", number))?],
+        cli::RttUpChannels::All => rtt.up_channels().drain().collect(),
+    };
+    if channels.is_empty() {
+        bail!("the target has no RTT up channels");
+    }
+    // stdin is only forwarded if asked for, so that it stays with whatever else runs
+    let input_channel = down_channel.and_then(|number| {
+        let channel = rtt.down_channels().take(number);
+        if channel.is_none() {
+            log::warn!("RTT down channel {} not found; ignoring stdin", number);
+        }
+        channel
+    });
+
+    Ok(Some((channels, input_channel)))
+}
+
+/// Returns `None` if the RTT control block was not initialized within `timeout`
+fn attach_rtt(
+    rtt_buffer_address: u32,
+    sess: Arc<Mutex<Session>>,
+    timeout: Duration,
+) -> anyhow::Result<Option<Rtt>> {
     let scan_region = ScanRegion::Exact(rtt_buffer_address);
-    for _ in 0..NUM_RETRIES {
+    let start = Instant::now();
+    loop {
         match Rtt::attach_region(sess.clone(), &scan_region) {
-            Ok(mut rtt) => {
+            Ok(rtt) => {
                 log::debug!("Successfully attached RTT");
-
-                let channel = rtt
-                    .up_channels()
-                    .take(0)
-                    .ok_or_else(|| anyhow!("RTT up channel 0 not found"))?;
-
-                return Ok(channel);
+                return Ok(Some(rtt));
             }
 
             Err(probe_rs_rtt::Error::ControlBlockNotFound) => {
@@ -317,13 +1295,91 @@ fn setup_logging_channel(
                 return Err(anyhow!(e));
             }
         }
+
+        if start.elapsed() >= timeout {
+            return Ok(None);
+        }
+
+        thread::sleep(Duration::from_millis(10));
     }
+}
 
-    log::error!("Max number of RTT attach retries exceeded.");
-    Err(anyhow!(probe_rs_rtt::Error::ControlBlockNotFound))
+/// Prints the number, name and buffer size of each of the program's RTT channels
+fn list_rtt_channels(
+    elf: &Elf,
+    sess: Arc<Mutex<Session>>,
+    opts: &cli::Opts,
+) -> anyhow::Result<i32> {
+    let address = elf
+        .rtt_buffer_address()
+        .ok_or_else(|| anyhow!("the program has no RTT control block (`_SEGGER_RTT`)"))?;
+    let timeout = Duration::from_millis(opts.rtt_attach_timeout);
+    let rtt = attach_rtt(address, sess.clone(), timeout)?;
+
+    sess.lock().unwrap().core(0)?.halt(TIMEOUT)?;
+
+    let mut rtt = rtt.ok_or_else(|| {
+        anyhow!(
+            "RTT control block was not initialized within {} ms; \
+            does the program initialize RTT (e.g. by logging)?",
+            opts.rtt_attach_timeout
+        )
+    })?;
+
+    println!("up channels:");
+    for channel in rtt.up_channels().iter() {
+        print_rtt_channel(channel.number(), channel.name(), channel.buffer_size());
+    }
+    println!("down channels:");
+    for channel in rtt.down_channels().iter() {
+        print_rtt_channel(channel.number(), channel.name(), channel.buffer_size());
+    }
+
+    Ok(0)
+}
+
+fn print_rtt_channel(number: usize, name: Option<&str>, buffer_size: usize) {
+    println!(
+        "{:>4}: {:<16} {} bytes",
+        number,
+        name.unwrap_or("<unnamed>"),
+        buffer_size
+    );
+}
+
+/// Reads stdin line by line on a separate thread, so that waiting for input never blocks the logs
+///
+/// The thread ends once stdin is closed
+fn spawn_stdin_reader() -> mpsc::Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut stdin = stdin.lock();
+        loop {
+            let mut line = vec![];
+            match stdin.read_until(b'\n', &mut line) {
+                Ok(0) => {
+                    log::debug!("stdin closed; no more input will be sent to the target");
+                    break;
+                }
+                Ok(_) => {
+                    if sender.send(line).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    log::warn!("failed to read stdin: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+    receiver
 }
 
-/// Print a line to separate different execution stages.
-fn print_separator() {
-    println!("{}", "─".repeat(80).dimmed());
+/// Print a line to separate different execution stages, unless `--quiet` is given.
+fn print_separator(quiet: bool) {
+    if !quiet {
+        println!("{}", "─".repeat(80).dimmed());
+    }
 }
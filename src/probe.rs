@@ -28,6 +28,16 @@ pub(crate) fn open(opts: &cli::Opts) -> Result<Probe, anyhow::Error> {
     let mut probe = filtered_probes[0].open()?;
     log::debug!("opened probe");
 
+    if let Some(protocol) = opts.protocol {
+        probe.select_protocol(protocol).map_err(|e| {
+            anyhow!(
+                "probe does not support the {:?} wire protocol: {}",
+                protocol,
+                e
+            )
+        })?;
+    }
+
     if let Some(speed) = opts.speed {
         probe.set_speed(speed)?;
     }
@@ -0,0 +1,202 @@
+//! Detection of read-out protection (RDP on STM32, APPROTECT on nRF), which makes flashing fail
+//! until the chip is mass-erased
+
+use probe_rs::{MemoryInterface as _, Session};
+
+/// Where a chip family keeps its read-out protection level, and how to interpret it
+struct Status {
+    address: u32,
+    is_locked: fn(u32) -> bool,
+    /// The register can't be read at all while the chip is locked
+    unreadable_if_locked: bool,
+}
+
+/// RDP levels other than 0 (`0xAA`) in the low byte of the register
+fn rdp_byte(value: u32) -> bool {
+    value & 0xff != 0xaa
+}
+
+/// The RDP level in bits 15:8 of `FLASH_OPTCR`
+fn rdp_optcr(value: u32) -> bool {
+    (value >> 8) & 0xff != 0xaa
+}
+
+/// `RDPRT` in `FLASH_OBR` on the F1
+fn rdprt_f1(value: u32) -> bool {
+    value & 1 << 1 != 0
+}
+
+/// `RDPRT[1:0]` in `FLASH_OBR` on the F0 and F3
+fn rdprt_f0(value: u32) -> bool {
+    value & 0b11 << 1 != 0
+}
+
+/// `UICR.APPROTECT`: erased (`0xFFFF_FFFF`) or `HwDisabled` (`0x5A`) leave debug access open
+fn approtect(value: u32) -> bool {
+    value != 0xffff_ffff && value != 0x5a
+}
+
+/// The status register of each chip family, keyed by chip name prefix
+const FAMILIES: &[(&str, Status)] = &[
+    (
+        "STM32F0",
+        Status {
+            address: 0x4002_201C,
+            is_locked: rdprt_f0,
+            unreadable_if_locked: false,
+        },
+    ),
+    (
+        "STM32F1",
+        Status {
+            address: 0x4002_201C,
+            is_locked: rdprt_f1,
+            unreadable_if_locked: false,
+        },
+    ),
+    (
+        "STM32F3",
+        Status {
+            address: 0x4002_201C,
+            is_locked: rdprt_f0,
+            unreadable_if_locked: false,
+        },
+    ),
+    (
+        "STM32F2",
+        Status {
+            address: 0x4002_3C14,
+            is_locked: rdp_optcr,
+            unreadable_if_locked: false,
+        },
+    ),
+    (
+        "STM32F4",
+        Status {
+            address: 0x4002_3C14,
+            is_locked: rdp_optcr,
+            unreadable_if_locked: false,
+        },
+    ),
+    (
+        "STM32F7",
+        Status {
+            address: 0x4002_3C14,
+            is_locked: rdp_optcr,
+            unreadable_if_locked: false,
+        },
+    ),
+    // `FLASH_OPTR`
+    (
+        "STM32G0",
+        Status {
+            address: 0x4002_2020,
+            is_locked: rdp_byte,
+            unreadable_if_locked: false,
+        },
+    ),
+    (
+        "STM32G4",
+        Status {
+            address: 0x4002_2020,
+            is_locked: rdp_byte,
+            unreadable_if_locked: false,
+        },
+    ),
+    (
+        "STM32L4",
+        Status {
+            address: 0x4002_2020,
+            is_locked: rdp_byte,
+            unreadable_if_locked: false,
+        },
+    ),
+    (
+        "STM32WB",
+        Status {
+            address: 0x5800_4020,
+            is_locked: rdp_byte,
+            unreadable_if_locked: false,
+        },
+    ),
+    // while APPROTECT is in effect the debugger can't access the memory bus at all
+    (
+        "NRF52",
+        Status {
+            address: 0x1000_1208,
+            is_locked: approtect,
+            unreadable_if_locked: true,
+        },
+    ),
+];
+
+/// Returns `Some(true)` if the chip is read-out protected and `None` if that can't be told, e.g.
+/// for chip families whose protection status is not known
+pub(crate) fn is_locked(sess: &mut Session, chip: &str) -> Option<bool> {
+    let status = status_of(chip)?;
+
+    let value = sess
+        .core(0)
+        .map_err(anyhow::Error::from)
+        .and_then(|mut core| {
+            core.read_word_32(status.address)
+                .map_err(anyhow::Error::from)
+        });
+    match value {
+        Ok(value) => Some((status.is_locked)(value)),
+        Err(e) if status.unreadable_if_locked => {
+            log::debug!(
+                "failed to read the APPROTECT status, so it is in effect: {}",
+                e
+            );
+            Some(true)
+        }
+        Err(e) => {
+            log::debug!("failed to read the read-out protection level: {}", e);
+            None
+        }
+    }
+}
+
+fn status_of(chip: &str) -> Option<&'static Status> {
+    let chip = chip.to_ascii_uppercase();
+    FAMILIES
+        .iter()
+        .find(|(family, _)| chip.starts_with(family))
+        .map(|(_, status)| status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rdp_level_0_is_unlocked() {
+        let status = status_of("STM32F401RETx").unwrap();
+        assert!(!(status.is_locked)(0x0fff_aaed));
+        assert!((status.is_locked)(0x0fff_55ed));
+
+        let status = status_of("STM32L476RGTx").unwrap();
+        assert!(!(status.is_locked)(0xffef_f8aa));
+        assert!((status.is_locked)(0xffef_f8bb));
+    }
+
+    #[test]
+    fn rdprt_bit_is_locked() {
+        let status = status_of("STM32F103C8").unwrap();
+        assert!(!(status.is_locked)(0x03ff_fffc));
+        assert!((status.is_locked)(0x03ff_fffe));
+    }
+
+    #[test]
+    fn erased_approtect_is_unlocked() {
+        let status = status_of("nRF52840_xxAA").unwrap();
+        assert!(!(status.is_locked)(0xffff_ffff));
+        assert!((status.is_locked)(0xffff_ff00));
+    }
+
+    #[test]
+    fn other_chips_have_no_known_status() {
+        assert!(status_of("LPC55S69").is_none());
+    }
+}
@@ -0,0 +1,67 @@
+//! Machine readable description of the connected probes and the selected chip
+
+use probe_rs::{config::MemoryRegion, DebugProbeInfo, Probe, WireProtocol};
+use serde_json::{json, Value};
+
+/// Prints the connected probes and, if given, the `chip` as a JSON document
+pub(crate) fn print_json(chip: Option<&str>) -> anyhow::Result<()> {
+    let probes = Probe::list_all().iter().map(probe).collect::<Vec<_>>();
+    let chip = chip.map(self::chip).transpose()?;
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&json!({ "probes": probes, "chip": chip }))?
+    );
+    Ok(())
+}
+
+/// Describes the probe; its capabilities are only known if it can be opened
+fn probe(info: &DebugProbeInfo) -> Value {
+    let mut probe = json!({
+        "identifier": info.identifier,
+        "vendor_id": format!("{:04x}", info.vendor_id),
+        "product_id": format!("{:04x}", info.product_id),
+        "serial_number": info.serial_number,
+        "type": format!("{:?}", info.probe_type),
+    });
+
+    match info.open() {
+        Ok(mut opened) => {
+            let protocols = [WireProtocol::Swd, WireProtocol::Jtag]
+                .iter()
+                .filter(|&&protocol| opened.select_protocol(protocol).is_ok())
+                .map(|protocol| format!("{:?}", protocol))
+                .collect::<Vec<_>>();
+
+            probe["name"] = json!(opened.get_name());
+            probe["protocols"] = json!(protocols);
+            probe["speed_khz"] = json!(opened.speed_khz());
+        }
+        // e.g. the probe is in use by another program
+        Err(e) => probe["error"] = json!(e.to_string()),
+    }
+
+    probe
+}
+
+fn chip(name: &str) -> anyhow::Result<Value> {
+    let target = probe_rs::config::get_target_by_name(name)?;
+    let memory_map = target
+        .memory_map
+        .iter()
+        .map(|region| {
+            let (kind, range) = match region {
+                MemoryRegion::Ram(ram) => ("ram", &ram.range),
+                MemoryRegion::Nvm(nvm) => ("nvm", &nvm.range),
+                MemoryRegion::Generic(generic) => ("generic", &generic.range),
+            };
+            json!({ "kind": kind, "start": range.start, "end": range.end })
+        })
+        .collect::<Vec<_>>();
+
+    Ok(json!({
+        "name": target.name,
+        "core": format!("{:?}", target.core_type),
+        "memory_map": memory_map,
+    }))
+}
@@ -1,32 +1,55 @@
 use std::collections::{btree_map, BTreeMap};
 
+use anyhow::bail;
 use gimli::{read::CfaRule, EndianSlice, LittleEndian, Register, RegisterRule};
 use probe_rs::{Core, CoreRegisterAddress, MemoryInterface};
 
 pub const LR: CoreRegisterAddress = CoreRegisterAddress(14);
 pub const PC: CoreRegisterAddress = CoreRegisterAddress(15);
 pub const SP: CoreRegisterAddress = CoreRegisterAddress(13);
+pub const XPSR: CoreRegisterAddress = CoreRegisterAddress(16);
 
 pub const LR_END: u32 = 0xFFFF_FFFF;
 
+/// Registers and memory of a halted program: a live core, or a snapshot of one
+pub trait Target {
+    fn read_core_reg(&mut self, reg: CoreRegisterAddress) -> anyhow::Result<u32>;
+    fn read_word_32(&mut self, address: u32) -> anyhow::Result<u32>;
+    fn read_32(&mut self, address: u32, data: &mut [u32]) -> anyhow::Result<()>;
+}
+
+impl Target for Core<'_> {
+    fn read_core_reg(&mut self, reg: CoreRegisterAddress) -> anyhow::Result<u32> {
+        Ok(Core::read_core_reg(self, reg)?)
+    }
+
+    fn read_word_32(&mut self, address: u32) -> anyhow::Result<u32> {
+        Ok(MemoryInterface::read_word_32(self, address)?)
+    }
+
+    fn read_32(&mut self, address: u32, data: &mut [u32]) -> anyhow::Result<()> {
+        Ok(MemoryInterface::read_32(self, address, data)?)
+    }
+}
+
 /// Cache and track the state of CPU registers while the stack is being unwound.
-pub struct Registers<'c, 'probe> {
+pub struct Registers<'c> {
     cache: BTreeMap<u16, u32>,
-    pub core: &'c mut Core<'probe>,
+    pub target: &'c mut dyn Target,
 }
 
-impl<'c, 'probe> Registers<'c, 'probe> {
-    pub fn new(lr: u32, sp: u32, core: &'c mut Core<'probe>) -> Self {
+impl<'c> Registers<'c> {
+    pub fn new(lr: u32, sp: u32, target: &'c mut dyn Target) -> Self {
         let mut cache = BTreeMap::new();
         cache.insert(LR.0, lr);
         cache.insert(SP.0, sp);
-        Self { cache, core }
+        Self { cache, target }
     }
 
     pub fn get(&mut self, reg: CoreRegisterAddress) -> anyhow::Result<u32> {
         Ok(match self.cache.entry(reg.0) {
             btree_map::Entry::Occupied(entry) => *entry.get(),
-            btree_map::Entry::Vacant(entry) => *entry.insert(self.core.read_core_reg(reg)?),
+            btree_map::Entry::Vacant(entry) => *entry.insert(self.target.read_core_reg(reg)?),
         })
     }
 
@@ -54,7 +77,7 @@ impl<'c, 'probe> Registers<'c, 'probe> {
                 Ok(changed)
             }
             // NOTE not encountered in practice so far
-            CfaRule::Expression(_) => todo!("CfaRule::Expression"),
+            CfaRule::Expression(_) => bail!("unsupported CFA rule: DWARF expression"),
         }
     }
 
@@ -67,10 +90,9 @@ impl<'c, 'probe> Registers<'c, 'probe> {
             RegisterRule::Offset(offset) => {
                 let cfa = self.get(SP)?;
                 let addr = (cfa as i64 + offset) as u32;
-                self.cache.insert(reg.0, self.core.read_word_32(addr)?);
+                self.cache.insert(reg.0, self.target.read_word_32(addr)?);
             }
-            RegisterRule::Undefined => unreachable!(),
-            _ => unimplemented!(),
+            rule => bail!("unsupported unwind rule for register {}: {:?}", reg.0, rule),
         }
         Ok(())
     }
@@ -79,3 +101,59 @@ impl<'c, 'probe> Registers<'c, 'probe> {
 fn gimli2probe(reg: &Register) -> CoreRegisterAddress {
     CoreRegisterAddress(reg.0)
 }
+
+#[cfg(test)]
+mod tests {
+    use gimli::Expression;
+
+    use super::*;
+
+    /// A target whose memory holds its address in every word
+    struct Memory;
+
+    impl Target for Memory {
+        fn read_core_reg(&mut self, reg: CoreRegisterAddress) -> anyhow::Result<u32> {
+            bail!("register r{} was not cached", reg.0)
+        }
+
+        fn read_word_32(&mut self, address: u32) -> anyhow::Result<u32> {
+            Ok(address)
+        }
+
+        fn read_32(&mut self, address: u32, data: &mut [u32]) -> anyhow::Result<()> {
+            for (i, word) in data.iter_mut().enumerate() {
+                *word = address + 4 * i as u32;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn offset_rule_reads_the_stack() {
+        let mut target = Memory;
+        let mut registers = Registers::new(0, 0x2000_0000, &mut target);
+
+        registers
+            .update(&Register(LR.0), &RegisterRule::Offset(4))
+            .unwrap();
+
+        assert_eq!(0x2000_0004, registers.get(LR).unwrap());
+    }
+
+    #[test]
+    fn unexpected_rules_are_errors() {
+        let mut target = Memory;
+        let mut registers = Registers::new(0, 0x2000_0000, &mut target);
+        let expression = Expression(EndianSlice::new(&[], LittleEndian));
+
+        assert!(registers
+            .update(&Register(LR.0), &RegisterRule::Undefined)
+            .is_err());
+        assert!(registers
+            .update(&Register(4), &RegisterRule::Expression(expression))
+            .is_err());
+        assert!(registers
+            .update_cfa(&CfaRule::Expression(expression))
+            .is_err());
+    }
+}
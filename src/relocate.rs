@@ -0,0 +1,131 @@
+//! Flashing of ELF images at an offset from their link-time addresses
+
+use anyhow::anyhow;
+use object::{
+    elf::PT_LOAD,
+    read::elf::{ElfFile32, ProgramHeader as _},
+    Endianness,
+};
+use probe_rs::{
+    config::MemoryRegion,
+    flashing::{DownloadOptions, FlashLoader},
+    Session,
+};
+
+/// Contents of a loadable segment, placed at its physical address plus the offset
+#[derive(Debug, PartialEq)]
+struct Segment<'data> {
+    address: u32,
+    data: &'data [u8],
+}
+
+/// Writes the loadable segments of the ELF file to the target's flash, `offset` bytes after their
+/// physical addresses
+///
+/// The ELF file itself is left untouched, so its link-time addresses can still be used for
+/// symbolication
+pub(crate) fn flash(
+    sess: &mut Session,
+    elf_bytes: &[u8],
+    offset: u32,
+    options: DownloadOptions,
+) -> anyhow::Result<()> {
+    let segments = segments(elf_bytes, offset)?;
+    let memory_map = sess.target().memory_map.to_vec();
+
+    for segment in &segments {
+        let end = segment
+            .address
+            .checked_add(segment.data.len() as u32)
+            .ok_or_else(|| anyhow!("`--flash-offset` {:#x} overflows the address space", offset))?;
+        let in_flash = memory_map.iter().any(|region| match region {
+            MemoryRegion::Nvm(nvm) => nvm.range.start <= segment.address && end <= nvm.range.end,
+            _ => false,
+        });
+        if !in_flash {
+            log::warn!(
+                "segment at {:#010x}..{:#010x} lies outside the chip's flash; \
+                is the `--flash-offset` right?",
+                segment.address,
+                end
+            );
+        }
+    }
+
+    let mut loader = FlashLoader::new(memory_map, false);
+    for segment in &segments {
+        loader.add_data(segment.address, segment.data)?;
+    }
+
+    loader.commit(sess, options)?;
+    Ok(())
+}
+
+fn segments(elf_bytes: &[u8], offset: u32) -> anyhow::Result<Vec<Segment>> {
+    let elf = ElfFile32::<Endianness>::parse(elf_bytes)?;
+    let endian = elf.endian();
+
+    let mut segments = vec![];
+    for header in elf.raw_segments() {
+        if header.p_type(endian) != PT_LOAD || header.p_filesz(endian) == 0 {
+            continue;
+        }
+
+        let data = header
+            .data(endian, elf_bytes)
+            .map_err(|_| anyhow!("ELF segment data is out of bounds"))?;
+        let address = header
+            .p_paddr(endian)
+            .checked_add(offset)
+            .ok_or_else(|| anyhow!("`--flash-offset` {:#x} overflows the address space", offset))?;
+
+        segments.push(Segment { address, data });
+    }
+
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use object::{read::elf::ProgramHeader as _, Object as _, ObjectSection as _};
+
+    use super::*;
+
+    /// Returns the load address of the `.data` section: where its initial values are in flash
+    fn data_lma(elf_bytes: &[u8]) -> u32 {
+        let elf = ElfFile32::<Endianness>::parse(elf_bytes).unwrap();
+        let endian = elf.endian();
+        let (offset, _) = elf.section_by_name(".data").unwrap().file_range().unwrap();
+
+        elf.raw_segments()
+            .iter()
+            .find(|header| {
+                let start = u64::from(header.p_offset(endian));
+                header.p_type(endian) == PT_LOAD
+                    && start <= offset
+                    && offset < start + u64::from(header.p_filesz(endian))
+            })
+            .map(|header| header.p_paddr(endian) + (offset as u32 - header.p_offset(endian)))
+            .unwrap()
+    }
+
+    #[test]
+    fn offset_is_added_to_every_segment() {
+        let elf_bytes = include_bytes!("../tests/test_elfs/hello");
+        let original = segments(elf_bytes, 0).unwrap();
+        let relocated = segments(elf_bytes, 0x1_0000).unwrap();
+
+        assert!(!original.is_empty());
+        assert_eq!(original.len(), relocated.len());
+        for (original, relocated) in original.iter().zip(&relocated) {
+            assert_eq!(original.address + 0x1_0000, relocated.address);
+            assert_eq!(original.data, relocated.data);
+        }
+
+        // `.data` is flashed at its load address, not at its RAM address
+        let data_lma = data_lma(elf_bytes);
+        assert!(relocated
+            .iter()
+            .any(|segment| segment.address == data_lma + 0x1_0000));
+    }
+}
@@ -0,0 +1,129 @@
+//! A minimal semihosting host that serves the target's `SYS_GET_CMDLINE` requests
+
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use probe_rs::{Core, CoreRegisterAddress, MemoryInterface as _};
+
+use crate::{
+    cortexm::{BKPT_SEMIHOSTING, BKPT_SIZE},
+    registers::PC,
+};
+
+const R0: CoreRegisterAddress = CoreRegisterAddress(0);
+const R1: CoreRegisterAddress = CoreRegisterAddress(1);
+
+const SYS_GET_CMDLINE: u32 = 0x15;
+const SYS_EXIT: u32 = 0x18;
+const SYS_EXIT_EXTENDED: u32 = 0x20;
+
+/// Value returned in `r0` when a request fails or isn't supported
+const ERROR: u32 = -1i32 as u32;
+
+/// A `--env` entry: `KEY=VALUE`
+#[derive(Debug, PartialEq)]
+pub(crate) struct EnvVar {
+    key: String,
+    value: String,
+}
+
+impl FromStr for EnvVar {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((key, value)) if !key.is_empty() => Ok(Self {
+                key: key.to_string(),
+                value: value.to_string(),
+            }),
+            _ => Err(anyhow!("invalid `--env {}`; expected `KEY=VALUE`", s)),
+        }
+    }
+}
+
+/// Whether the target was halted by a semihosting request that has been answered
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Request {
+    /// The request was served; the core must be resumed
+    Served,
+    /// The core halted for some other reason, or the program asked to exit
+    NotServed,
+}
+
+/// Serves the semihosting request the halted `core` is waiting on, if any
+///
+/// `SYS_GET_CMDLINE` is answered with the `env` entries, separated by spaces. `SYS_EXIT` is left to
+/// the caller, like any other halt. All other operations fail with `-1`.
+pub(crate) fn serve(core: &mut Core, env: &[EnvVar]) -> anyhow::Result<Request> {
+    let pc = core.read_core_reg(PC)?;
+    let mut instruction = [0; 2];
+    core.read_8(pc, &mut instruction)?;
+    if u16::from_le_bytes(instruction) != BKPT_SEMIHOSTING {
+        return Ok(Request::NotServed);
+    }
+
+    let operation = core.read_core_reg(R0)?;
+    let parameter = core.read_core_reg(R1)?;
+    let result = match operation {
+        SYS_EXIT | SYS_EXIT_EXTENDED => return Ok(Request::NotServed),
+        SYS_GET_CMDLINE => get_cmdline(core, parameter, &cmdline(env))?,
+        _ => {
+            log::warn!("unsupported semihosting operation {:#04x}", operation);
+            ERROR
+        }
+    };
+
+    core.write_core_reg(R0, result)?;
+    core.write_core_reg(PC, pc + BKPT_SIZE)?;
+
+    Ok(Request::Served)
+}
+
+/// Writes `cmdline` into the buffer described by the `{ pointer, length }` block at `parameter`
+fn get_cmdline(core: &mut Core, parameter: u32, cmdline: &str) -> anyhow::Result<u32> {
+    let buffer = core.read_word_32(parameter)?;
+    let len = core.read_word_32(parameter + 4)?;
+
+    // the string must fit, including its NUL terminator
+    if cmdline.len() >= len as usize {
+        return Ok(ERROR);
+    }
+
+    let mut bytes = cmdline.as_bytes().to_vec();
+    bytes.push(0);
+    core.write_8(buffer, &bytes)?;
+    core.write_word_32(parameter + 4, cmdline.len() as u32)?;
+
+    Ok(0)
+}
+
+fn cmdline(env: &[EnvVar]) -> String {
+    env.iter()
+        .map(|var| format!("{}={}", var.key, var.value))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_key_and_value() {
+        let expected = EnvVar {
+            key: "BAUD".to_string(),
+            value: "115200=fast".to_string(),
+        };
+        assert_eq!(expected, "BAUD=115200=fast".parse().unwrap());
+
+        assert!("BAUD".parse::<EnvVar>().is_err());
+        assert!("=115200".parse::<EnvVar>().is_err());
+    }
+
+    #[test]
+    fn cmdline_joins_env() {
+        let env = ["A=1".parse().unwrap(), "B=".parse().unwrap()];
+
+        assert_eq!("A=1 B=", cmdline(&env));
+    }
+}
@@ -1,6 +1,6 @@
 use std::{mem, ops::Range};
 
-use probe_rs::{Core, MemoryInterface};
+use crate::registers::Target;
 
 /// Registers stacked on exception entry.
 #[derive(Debug)]
@@ -41,11 +41,11 @@ impl Stacked {
     /// Number of 32-bit words stacked in an extended frame.
     const WORDS_EXTENDED: usize = Self::WORDS_BASIC + 17; // 16 FPU regs + 1 status word
 
-    /// Reads stacked registers from RAM
+    /// Reads stacked registers from RAM, or a snapshot of it
     ///
     /// This performs bound checks and returns `None` if a invalid memory read is requested
     pub fn read(
-        core: &mut Core<'_>,
+        target: &mut dyn Target,
         sp: u32,
         fpu: bool,
         ram_bounds: Range<u32>,
@@ -64,7 +64,7 @@ impl Stacked {
             return Ok(None);
         }
 
-        core.read_32(start, registers)?;
+        target.read_32(start, registers)?;
 
         Ok(Some(Stacked {
             lr: registers[0],
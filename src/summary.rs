@@ -0,0 +1,67 @@
+//! One-line summary of a run, easy to grep for across many CI logs
+
+use std::{fmt, time::Duration};
+
+pub(crate) struct Summary<'a> {
+    /// Why the program stopped, e.g. `ok` or `panic`
+    pub(crate) exit: &'a str,
+    /// Number of resolved frames and of all frames; `None` if no backtrace was printed
+    pub(crate) frames: Option<(usize, usize)>,
+    pub(crate) num_logs: usize,
+    pub(crate) duration: Duration,
+}
+
+impl fmt::Display for Summary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "probe-run: exit={}", self.exit)?;
+        if let Some((num_resolved, num_frames)) = self.frames {
+            write!(
+                f,
+                " frames={}({} unknown)",
+                num_frames,
+                num_frames - num_resolved
+            )?;
+        }
+        write!(
+            f,
+            " logs={} duration={:.1}s",
+            self.num_logs,
+            self.duration.as_secs_f64()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_with_backtrace() {
+        let summary = Summary {
+            exit: "panic",
+            frames: Some((12, 14)),
+            num_logs: 312,
+            duration: Duration::from_millis(4_240),
+        };
+
+        assert_eq!(
+            "probe-run: exit=panic frames=14(2 unknown) logs=312 duration=4.2s",
+            summary.to_string()
+        );
+    }
+
+    #[test]
+    fn summary_without_backtrace() {
+        let summary = Summary {
+            exit: "ok",
+            frames: None,
+            num_logs: 0,
+            duration: Duration::from_millis(900),
+        };
+
+        assert_eq!(
+            "probe-run: exit=ok logs=0 duration=0.9s",
+            summary.to_string()
+        );
+    }
+}
@@ -0,0 +1,351 @@
+//! Reporting of the ELF flags, ARM build attributes and DWARF versions the program was built with
+
+use std::str;
+
+use anyhow::{anyhow, bail};
+use object::{FileFlags, Object as _, ObjectSection as _};
+
+use crate::{cortexm, elf::Elf};
+
+const EF_ARM_ABI_FLOAT_SOFT: u32 = 0x200;
+const EF_ARM_ABI_FLOAT_HARD: u32 = 0x400;
+
+const FORMAT_VERSION: u8 = b'A';
+const TAG_FILE: u64 = 1;
+
+const TAG_CPU_NAME: u64 = 5;
+const TAG_CPU_ARCH: u64 = 6;
+const TAG_CPU_ARCH_PROFILE: u64 = 7;
+const TAG_THUMB_ISA_USE: u64 = 9;
+const TAG_FP_ARCH: u64 = 10;
+const TAG_ABI_VFP_ARGS: u64 = 28;
+const TAG_COMPATIBILITY: u64 = 32;
+
+/// A public ("aeabi") build attribute that applies to the whole file
+#[derive(Debug, PartialEq)]
+struct Attribute {
+    tag: u64,
+    value: Value,
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    Int(u64),
+    Str(String),
+}
+
+/// Prints the metadata the ELF file carries about the target it was built for
+pub(crate) fn print(elf: &Elf) -> anyhow::Result<()> {
+    log::info!("target info:");
+
+    if let FileFlags::Elf { e_flags } = elf.flags() {
+        log::info!(
+            "  ELF flags: {:#010x} (EABI version {}, {})",
+            e_flags,
+            e_flags >> 24,
+            float_abi(e_flags)
+        );
+    }
+
+    match elf.section_by_name(".ARM.attributes") {
+        Some(section) => {
+            for attribute in parse_attributes(section.data()?)? {
+                if let Some((name, value)) = describe(&attribute) {
+                    log::info!("  {}: {}", name, value);
+                }
+            }
+        }
+        None => log::info!("  no ARM build attributes found"),
+    }
+
+    for (name, version) in dwarf_versions(elf)? {
+        log::info!("  DWARF v{}: {}", version, name);
+    }
+
+    Ok(())
+}
+
+fn float_abi(e_flags: u32) -> &'static str {
+    if e_flags & EF_ARM_ABI_FLOAT_HARD != 0 {
+        "hard-float ABI"
+    } else if e_flags & EF_ARM_ABI_FLOAT_SOFT != 0 {
+        "soft-float ABI"
+    } else {
+        "float ABI not specified"
+    }
+}
+
+/// Returns the name and DWARF version of each compilation unit
+fn dwarf_versions(elf: &Elf) -> anyhow::Result<Vec<(String, u16)>> {
+    let debug_info = gimli::DebugInfo::new(elf.section_data(".debug_info")?, cortexm::ENDIANNESS);
+    let debug_abbrev =
+        gimli::DebugAbbrev::new(elf.section_data(".debug_abbrev")?, cortexm::ENDIANNESS);
+    let debug_str = gimli::DebugStr::new(elf.section_data(".debug_str")?, cortexm::ENDIANNESS);
+
+    let mut versions = vec![];
+    let mut units = debug_info.units();
+    while let Some(header) = units.next()? {
+        let abbreviations = header.abbreviations(&debug_abbrev)?;
+        let mut entries = header.entries(&abbreviations);
+
+        let name = match entries.next_dfs()? {
+            Some((_, entry)) => match entry.attr_value(gimli::DW_AT_name)? {
+                Some(gimli::AttributeValue::String(name)) => Some(name),
+                Some(gimli::AttributeValue::DebugStrRef(offset)) => debug_str.get_str(offset).ok(),
+                _ => None,
+            },
+            None => None,
+        };
+        let name = name
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "<unnamed unit>".to_string());
+
+        versions.push((name, header.version()));
+    }
+
+    Ok(versions)
+}
+
+/// Parses the file-scope attributes of the `.ARM.attributes` section
+fn parse_attributes(data: &[u8]) -> anyhow::Result<Vec<Attribute>> {
+    let mut reader = Reader(data);
+    if reader.u8()? != FORMAT_VERSION {
+        bail!("unsupported `.ARM.attributes` format");
+    }
+
+    let mut attributes = vec![];
+    while !reader.0.is_empty() {
+        let start = reader.0;
+        let len = reader.u32()? as usize;
+        let mut subsection = Reader(start.get(4..len).ok_or_else(truncated)?);
+        reader.0 = &start[len..];
+
+        if subsection.ntbs()? != "aeabi" {
+            continue;
+        }
+
+        while !subsection.0.is_empty() {
+            let start = subsection.0;
+            let tag = subsection.uleb()?;
+            let size = subsection.u32()? as usize;
+            let header_size = start.len() - subsection.0.len();
+            let mut subsubsection = Reader(start.get(header_size..size).ok_or_else(truncated)?);
+            subsection.0 = &start[size..];
+
+            if tag != TAG_FILE {
+                continue;
+            }
+
+            while !subsubsection.0.is_empty() {
+                let tag = subsubsection.uleb()?;
+                let value = match tag {
+                    4 | TAG_CPU_NAME => Value::Str(subsubsection.ntbs()?.to_string()),
+                    TAG_COMPATIBILITY => {
+                        subsubsection.uleb()?;
+                        Value::Str(subsubsection.ntbs()?.to_string())
+                    }
+                    // tags without a fixed meaning: even ones are integers, odd ones strings
+                    tag if tag > TAG_COMPATIBILITY && tag % 2 == 1 => {
+                        Value::Str(subsubsection.ntbs()?.to_string())
+                    }
+                    _ => Value::Int(subsubsection.uleb()?),
+                };
+                attributes.push(Attribute { tag, value });
+            }
+        }
+    }
+
+    Ok(attributes)
+}
+
+/// Returns a human readable name and value of the attributes relevant to Cortex-M targets
+fn describe(attribute: &Attribute) -> Option<(&'static str, String)> {
+    let (name, value) = match (attribute.tag, &attribute.value) {
+        (TAG_CPU_NAME, Value::Str(name)) => ("CPU", name.clone()),
+        (TAG_CPU_ARCH, Value::Int(arch)) => ("architecture", cpu_arch(*arch).to_string()),
+        (TAG_CPU_ARCH_PROFILE, Value::Int(profile)) => {
+            let profile = match *profile as u8 {
+                0 => "not applicable",
+                b'A' => "application (A)",
+                b'R' => "real-time (R)",
+                b'M' => "microcontroller (M)",
+                b'S' => "classic (S)",
+                _ => "unknown",
+            };
+            ("profile", profile.to_string())
+        }
+        (TAG_THUMB_ISA_USE, Value::Int(thumb)) => {
+            let thumb = match thumb {
+                0 => "not permitted",
+                1 => "16-bit Thumb",
+                2 => "32-bit Thumb",
+                3 => "as permitted by the architecture",
+                _ => "unknown",
+            };
+            ("Thumb", thumb.to_string())
+        }
+        (TAG_FP_ARCH, Value::Int(fp)) => {
+            let fp = match fp {
+                0 => "none",
+                1 => "VFPv1",
+                2 => "VFPv2",
+                3 => "VFPv3",
+                4 => "VFPv3-D16",
+                5 => "VFPv4",
+                6 => "VFPv4-D16",
+                7 => "FP-ARMv8",
+                8 => "FP-ARMv8-D16",
+                _ => "unknown",
+            };
+            ("FPU", fp.to_string())
+        }
+        (TAG_ABI_VFP_ARGS, Value::Int(args)) => {
+            let args = match args {
+                0 => "core registers (soft-float)",
+                1 => "VFP registers (hard-float)",
+                2 => "toolchain specific",
+                3 => "compatible with both",
+                _ => "unknown",
+            };
+            ("float arguments", args.to_string())
+        }
+        _ => return None,
+    };
+
+    Some((name, value))
+}
+
+fn cpu_arch(arch: u64) -> &'static str {
+    match arch {
+        0 => "pre-v4",
+        1 => "v4",
+        2 => "v4T",
+        3 => "v5T",
+        4 => "v5TE",
+        5 => "v5TEJ",
+        6 => "v6",
+        7 => "v6KZ",
+        8 => "v6T2",
+        9 => "v6K",
+        10 => "v7",
+        11 => "v6-M",
+        12 => "v6S-M",
+        13 => "v7E-M",
+        14 => "v8-A",
+        15 => "v8-R",
+        16 => "v8-M.baseline",
+        17 => "v8-M.mainline",
+        18 => "v8.1-A",
+        19 => "v8.2-A",
+        20 => "v8.3-A",
+        21 => "v8.1-M.mainline",
+        _ => "unknown",
+    }
+}
+
+fn truncated() -> anyhow::Error {
+    anyhow!("`.ARM.attributes` section is truncated")
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        let (&byte, rest) = self.0.split_first().ok_or_else(truncated)?;
+        self.0 = rest;
+        Ok(byte)
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        let mut bytes = [0; 4];
+        for byte in &mut bytes {
+            *byte = self.u8()?;
+        }
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn uleb(&mut self) -> anyhow::Result<u64> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+            if shift >= 64 {
+                bail!("ULEB128 number in `.ARM.attributes` doesn't fit in 64 bits");
+            }
+        }
+    }
+
+    /// Reads a NUL-terminated string
+    fn ntbs(&mut self) -> anyhow::Result<&'a str> {
+        let end = self
+            .0
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or_else(truncated)?;
+        let s = str::from_utf8(&self.0[..end])?;
+        self.0 = &self.0[end + 1..];
+        Ok(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The attributes `rustc` emits for `thumbv7em-none-eabihf`
+    fn cortex_m4f_attributes() -> Vec<u8> {
+        let attributes = b"\x05Cortex-M4\0\x06\x0d\x07M\x09\x02\x0a\x06\x1c\x01";
+        let subsubsection_len = 1 + 4 + attributes.len();
+        let subsection_len = 4 + b"aeabi\0".len() + subsubsection_len;
+
+        let mut data = vec![FORMAT_VERSION];
+        data.extend(&(subsection_len as u32).to_le_bytes());
+        data.extend(b"aeabi\0");
+        data.push(TAG_FILE as u8);
+        data.extend(&(subsubsection_len as u32).to_le_bytes());
+        data.extend(attributes);
+        data
+    }
+
+    #[test]
+    fn parses_file_attributes() {
+        let attributes = parse_attributes(&cortex_m4f_attributes()).unwrap();
+
+        let described = attributes.iter().filter_map(describe).collect::<Vec<_>>();
+        let expected = vec![
+            ("CPU", "Cortex-M4".to_string()),
+            ("architecture", "v7E-M".to_string()),
+            ("profile", "microcontroller (M)".to_string()),
+            ("Thumb", "32-bit Thumb".to_string()),
+            ("FPU", "VFPv4-D16".to_string()),
+            ("float arguments", "VFP registers (hard-float)".to_string()),
+        ];
+
+        assert_eq!(expected, described);
+    }
+
+    #[test]
+    fn truncated_section_is_rejected() {
+        let data = cortex_m4f_attributes();
+
+        assert!(parse_attributes(&data[..data.len() - 3]).is_err());
+    }
+
+    #[test]
+    fn overlong_uleb_is_rejected() {
+        let data = [0xff; 11];
+
+        assert!(Reader(&data).uleb().is_err());
+    }
+
+    #[test]
+    fn float_abi_from_flags() {
+        assert_eq!("hard-float ABI", float_abi(0x0500_0400));
+        assert_eq!("soft-float ABI", float_abi(0x0500_0200));
+    }
+}
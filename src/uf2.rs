@@ -0,0 +1,145 @@
+//! Parsing and flashing of UF2 images
+
+use std::convert::TryInto;
+
+use anyhow::{anyhow, bail};
+use probe_rs::{
+    flashing::{DownloadOptions, FlashLoader},
+    Session,
+};
+
+const BLOCK_SIZE: usize = 512;
+const MAX_PAYLOAD_SIZE: usize = 476;
+
+const MAGIC_START0: u32 = 0x0A32_4655; // "UF2\n"
+const MAGIC_START1: u32 = 0x9E5D_5157;
+const MAGIC_END: u32 = 0x0AB1_6F30;
+
+/// The block is not meant for the main flash and must be skipped
+const FLAG_NOT_MAIN_FLASH: u32 = 0x0000_0001;
+
+/// Payload of a single UF2 block
+#[derive(Debug, PartialEq)]
+pub(crate) struct Block {
+    pub(crate) address: u32,
+    pub(crate) data: Vec<u8>,
+}
+
+/// Returns `true` if `bytes` start with a UF2 block header
+pub(crate) fn is_uf2(bytes: &[u8]) -> bool {
+    bytes.len() >= 8 && read_word(bytes, 0) == MAGIC_START0 && read_word(bytes, 4) == MAGIC_START1
+}
+
+/// Extracts the payload of all blocks that target the main flash
+pub(crate) fn parse(bytes: &[u8]) -> anyhow::Result<Vec<Block>> {
+    if bytes.len() % BLOCK_SIZE != 0 {
+        bail!(
+            "UF2 image size ({} bytes) is not a multiple of the block size ({} bytes)",
+            bytes.len(),
+            BLOCK_SIZE
+        );
+    }
+
+    let mut blocks = vec![];
+    for (index, block) in bytes.chunks_exact(BLOCK_SIZE).enumerate() {
+        if read_word(block, 0) != MAGIC_START0
+            || read_word(block, 4) != MAGIC_START1
+            || read_word(block, BLOCK_SIZE - 4) != MAGIC_END
+        {
+            bail!("UF2 block {} has invalid magic numbers", index);
+        }
+
+        let flags = read_word(block, 8);
+        if flags & FLAG_NOT_MAIN_FLASH != 0 {
+            continue;
+        }
+
+        let address = read_word(block, 12);
+        let payload_size = read_word(block, 16) as usize;
+        if payload_size > MAX_PAYLOAD_SIZE {
+            return Err(anyhow!(
+                "UF2 block {} has an oversized payload ({} bytes)",
+                index,
+                payload_size
+            ));
+        }
+
+        blocks.push(Block {
+            address,
+            data: block[32..32 + payload_size].to_vec(),
+        });
+    }
+
+    Ok(blocks)
+}
+
+/// Writes the UF2 `blocks` to the target's flash
+pub(crate) fn flash(
+    sess: &mut Session,
+    blocks: &[Block],
+    options: DownloadOptions,
+) -> anyhow::Result<()> {
+    let mut loader = FlashLoader::new(sess.target().memory_map.to_vec(), false);
+    for block in blocks {
+        loader.add_data(block.address, &block.data)?;
+    }
+
+    loader.commit(sess, options)?;
+    Ok(())
+}
+
+fn read_word(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(flags: u32, address: u32, payload: &[u8]) -> Vec<u8> {
+        let mut block = vec![0; BLOCK_SIZE];
+        block[0..4].copy_from_slice(&MAGIC_START0.to_le_bytes());
+        block[4..8].copy_from_slice(&MAGIC_START1.to_le_bytes());
+        block[8..12].copy_from_slice(&flags.to_le_bytes());
+        block[12..16].copy_from_slice(&address.to_le_bytes());
+        block[16..20].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+        block[32..32 + payload.len()].copy_from_slice(payload);
+        block[BLOCK_SIZE - 4..].copy_from_slice(&MAGIC_END.to_le_bytes());
+        block
+    }
+
+    #[test]
+    fn parses_main_flash_blocks() {
+        let mut image = block(0, 0x1000_0000, &[1, 2, 3, 4]);
+        image.extend(block(FLAG_NOT_MAIN_FLASH, 0x2000_0000, &[5, 6]));
+        image.extend(block(0, 0x1000_0100, &[7; 256]));
+
+        assert!(is_uf2(&image));
+
+        let blocks = parse(&image).unwrap();
+        let expected = vec![
+            Block {
+                address: 0x1000_0000,
+                data: vec![1, 2, 3, 4],
+            },
+            Block {
+                address: 0x1000_0100,
+                data: vec![7; 256],
+            },
+        ];
+
+        assert_eq!(expected, blocks);
+    }
+
+    #[test]
+    fn elf_is_not_uf2() {
+        assert!(!is_uf2(b"\x7fELF\x01\x01\x01\x00"));
+    }
+
+    #[test]
+    fn truncated_image_is_rejected() {
+        let image = block(0, 0x1000_0000, &[1, 2, 3, 4]);
+
+        assert!(parse(&image[..BLOCK_SIZE - 1]).is_err());
+    }
+}
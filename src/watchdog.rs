@@ -0,0 +1,191 @@
+//! `--disable-watchdog`: keeping the watchdogs of STM32 chips from resetting the core while it is
+//! halted by the debugger
+
+use anyhow::bail;
+use probe_rs::{MemoryInterface as _, Session};
+
+/// `DBG_WWDG_STOP` and `DBG_IWDG_STOP` in `DBGMCU_APB1_FZ`
+const APB1_FZ_WATCHDOGS: u32 = 1 << 11 | 1 << 12;
+
+/// A debug register whose bits freeze the watchdogs while the core is halted
+#[derive(Debug, PartialEq)]
+struct FreezeRegister {
+    address: u32,
+    mask: u32,
+}
+
+/// The freeze register of each chip family, keyed by chip name prefix
+const FAMILIES: &[(&str, FreezeRegister)] = &[
+    // `DBGMCU_APB1_FZ` on the APB bus
+    (
+        "STM32F0",
+        FreezeRegister {
+            address: 0x4001_5808,
+            mask: APB1_FZ_WATCHDOGS,
+        },
+    ),
+    (
+        "STM32G0",
+        FreezeRegister {
+            address: 0x4001_5808,
+            mask: APB1_FZ_WATCHDOGS,
+        },
+    ),
+    (
+        "STM32L0",
+        FreezeRegister {
+            address: 0x4001_5808,
+            mask: APB1_FZ_WATCHDOGS,
+        },
+    ),
+    // `DBG_IWDG_STOP` and `DBG_WWDG_STOP` in `DBGMCU_CR`
+    (
+        "STM32F1",
+        FreezeRegister {
+            address: 0xE004_2004,
+            mask: 1 << 8 | 1 << 9,
+        },
+    ),
+    // `DBGMCU_APB1_FZ` (`DBGMCU_APB1FZR1` on the L4 and G4) on the PPB
+    (
+        "STM32F2",
+        FreezeRegister {
+            address: 0xE004_2008,
+            mask: APB1_FZ_WATCHDOGS,
+        },
+    ),
+    (
+        "STM32F3",
+        FreezeRegister {
+            address: 0xE004_2008,
+            mask: APB1_FZ_WATCHDOGS,
+        },
+    ),
+    (
+        "STM32F4",
+        FreezeRegister {
+            address: 0xE004_2008,
+            mask: APB1_FZ_WATCHDOGS,
+        },
+    ),
+    (
+        "STM32F7",
+        FreezeRegister {
+            address: 0xE004_2008,
+            mask: APB1_FZ_WATCHDOGS,
+        },
+    ),
+    (
+        "STM32G4",
+        FreezeRegister {
+            address: 0xE004_2008,
+            mask: APB1_FZ_WATCHDOGS,
+        },
+    ),
+    (
+        "STM32L1",
+        FreezeRegister {
+            address: 0xE004_2008,
+            mask: APB1_FZ_WATCHDOGS,
+        },
+    ),
+    (
+        "STM32L4",
+        FreezeRegister {
+            address: 0xE004_2008,
+            mask: APB1_FZ_WATCHDOGS,
+        },
+    ),
+    // `DBGMCU_APB1FZR1` of the WB is further up
+    (
+        "STM32WB",
+        FreezeRegister {
+            address: 0xE004_203C,
+            mask: APB1_FZ_WATCHDOGS,
+        },
+    ),
+];
+
+/// Sets the bits that freeze the independent and window watchdogs while the core is halted
+///
+/// The IWDG can't be stopped once it runs, so this is as far as the debugger can go; the program
+/// still has to feed the watchdogs while it runs.
+pub(crate) fn disable(sess: &mut Session, chip: &str) -> anyhow::Result<()> {
+    let register = match freeze_register(chip) {
+        Some(register) => register,
+        None => bail!(
+            "`--disable-watchdog` does not know how to freeze the watchdogs of `{}`; supported \
+             chip families are {}",
+            chip,
+            FAMILIES
+                .iter()
+                .map(|(family, _)| *family)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+
+    log::warn!(
+        "freezing the watchdogs while the core is halted, as requested by `--disable-watchdog`; \
+         the program may behave differently than it does without a debugger"
+    );
+
+    let mut core = sess.core(0)?;
+    let value = core.read_word_32(register.address)?;
+    core.write_word_32(register.address, value | register.mask)?;
+    log::debug!(
+        "set {:#010x} in the freeze register at {:#010x}",
+        register.mask,
+        register.address
+    );
+
+    Ok(())
+}
+
+fn freeze_register(chip: &str) -> Option<&'static FreezeRegister> {
+    let chip = chip.to_ascii_uppercase();
+    FAMILIES
+        .iter()
+        .find(|(family, _)| chip.starts_with(family))
+        .map(|(_, register)| register)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn family_is_found_by_chip_name_prefix() {
+        assert_eq!(
+            Some(&FreezeRegister {
+                address: 0xE004_2008,
+                mask: 1 << 11 | 1 << 12,
+            }),
+            freeze_register("STM32F401RETx")
+        );
+        assert_eq!(
+            Some(&FreezeRegister {
+                address: 0xE004_2004,
+                mask: 1 << 8 | 1 << 9,
+            }),
+            freeze_register("stm32f103c8")
+        );
+    }
+
+    #[test]
+    fn wb_freeze_register_is_apb1fzr1() {
+        assert_eq!(
+            Some(&FreezeRegister {
+                address: 0xE004_203C,
+                mask: 1 << 11 | 1 << 12,
+            }),
+            freeze_register("STM32WB55RGVx")
+        );
+    }
+
+    #[test]
+    fn other_chips_have_no_freeze_register() {
+        assert_eq!(None, freeze_register("nRF52840_xxAA"));
+        assert_eq!(None, freeze_register("STM32H743ZITx"));
+    }
+}
@@ -52,6 +52,9 @@ fn run_and_truncate(args: &str) -> RunResult {
             && !line.starts_with("     Running `")
             && !line.starts_with("    Blocking waiting for file lock ")
             && !line.starts_with("   Compiling probe-run v")
+            // flashing progress depends on the chip's sector and page layout
+            && !line.starts_with("erased ")
+            && !line.starts_with("flashed ")
             // TODO don't drop the `└─ probe_run @ ...` locations after
             // https://github.com/knurling-rs/probe-run/issues/217 is resolved
             && !line.starts_with("└─ ")